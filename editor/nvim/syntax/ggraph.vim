@@ -14,12 +14,17 @@ syntax match GgraphNumber /\v(0x[0-9a-fA-F]+|0\d+|\d+(\.\d+)?)/
 " Inner Functions @function_name
 syntax match GgraphFunction /@\w\+/
 
-" Top level Elements #define, #root, #grid
+" Top level Elements #define, #root, #grid, #legend
 syntax match GgraphRoot /#root/
 syntax match GgraphDefine /#define/
 syntax match GgraphGrid /#grid/
+syntax match GgraphLegend /#legend/
 syntax match GgraphEnd /#end/
 
+" Compile-time directives $var, $for and variables $name
+syntax match GgraphComptime /\$\(var\|for\)\>/
+syntax match GgraphComptimeVar /\$\(\(var\|for\)\>\)\@!\w\+/
+
 
 
 
@@ -33,4 +38,7 @@ highlight default link GgraphElement Keyword
 highlight default link GgraphRoot Keyword
 highlight default link GgraphDefine Keyword
 highlight default link GgraphGrid Keyword
+highlight default link GgraphLegend Keyword
 highlight default link GgraphEnd Keyword
+highlight default link GgraphComptime PreProc
+highlight default link GgraphComptimeVar Identifier
@@ -0,0 +1,31 @@
+#define quarter
+    categories "Q1", "Q2", "Q3", "Q4"
+#end
+#define sales
+    name "sales (k€)"
+    max 10
+#end
+#root
+    box 0, 0, 200, 150
+    color 0xebdbb2
+    background 0x282828
+    axis quarter, sales
+    title "Sales per quarter"
+#end
+#grid
+    color 0x3c3836
+    ystep 2
+#end
+#legend
+    position "top-left"
+#end
+@bars
+    values 3, 4.5, 2.5, 6
+    color 0x458588
+    name "2023"
+#end
+@bars
+    values 3.5, 5, 3, 7.5
+    color 0xd79921
+    name "2024"
+#end
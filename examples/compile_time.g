@@ -1,15 +1,32 @@
 % compile time variables %
-$var fg 0x000000
+$var fg 0xebdbb2
 
+#define x
+    min: 0
+    max: 100
+    name: "x"
+#end
 
+#define y
+    min: 0
+    max: 100
+    name: "y"
+#end
+
+#root
+    box: 0, 0, 100, 100
+    color: $fg
+    background: 0x282828
+    axis: x, y
+#end
 
 % compile time for loop %
 
-$for 1..5 | i -> (
+$for 20..=100 step 20 | i -> (
     @line
         name: ."L{i}"
         color: $fg
-        from: 0, i 
+        from: 0, i
         to: i, 0
     #end
 )
@@ -0,0 +1,28 @@
+#define time
+    name "time (h)"
+    max 10
+#end
+#define temperature
+    name "temperature (°C)"
+    min 10
+    max 30
+#end
+#root
+    box 0, 0, 150, 150
+    color 0xebdbb2
+    background 0x282828
+    axis time, temperature
+#end
+#legend
+#end
+@scatter
+    data "measures.csv"
+    columns "time", "temperature"
+    color 0x8ec07c
+    name "sensor"
+#end
+@graph
+    func "20 + 6 * sin(x / 1.6)"
+    color 0xfe8019
+    name "model"
+#end
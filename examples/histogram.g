@@ -0,0 +1,28 @@
+#define height
+    name "height (cm)"
+#end
+#define share
+    format "{:.2}"
+#end
+#root
+    box 0, 0, 200, 150
+    color 0xebdbb2
+    background 0x282828
+    axis height, share
+#end
+#grid
+    color 0x3c3836
+#end
+@histogram
+    data "heights.csv"
+    column "height"
+    bins "fd"
+    density true
+    color 0x83a598
+    alpha 0.8
+#end
+@graph
+    func "exp(-0.5 * ((x - 172) / 8)^2) / (8 * sqrt(2 * 3.14159))"
+    color 0xfe8019
+    thickness 1.5
+#end
@@ -0,0 +1,37 @@
+#define x
+    min 0
+    max 10
+    name "x"
+#end
+#define y
+    min -1.5
+    max 1.5
+    name "y"
+#end
+#root
+    box 0, 0, 200, 100
+    color 0xebdbb2
+    background 0x282828
+    axis x, y
+    title "Waves"
+    subtitle "sine and cosine"
+#end
+#legend
+    position "right"
+#end
+@graph
+    func "sin(x)"
+    color 0xfabd2f
+    name "sin(x)"
+#end
+@graph
+    func "cos(x)"
+    color 0x83a598
+    thickness 2
+    name "cos(x)"
+#end
+@point
+    at 5, 1
+    color 0xfb4934
+    name "peak"
+#end
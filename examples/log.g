@@ -0,0 +1,31 @@
+#define x
+    min 1
+    max 10000
+    scale "log"
+    minor 8
+    name "frequency (Hz)"
+#end
+#define y
+    min -100
+    max 100
+    scale "symlog"
+    threshold 1
+    name "gain"
+#end
+#root
+    box 0, 0, 240, 160
+    color 0xebdbb2
+    background 0x282828
+    axis x, y
+#end
+#grid
+    color 0x3c3836
+#end
+@graph
+    func "20 * log(x / 100)"
+    color 0xfabd2f
+#end
+@graph
+    func "-sqrt(x)"
+    color 0x83a598
+#end
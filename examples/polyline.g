@@ -0,0 +1,34 @@
+#define x
+    max 10
+#end
+#define y
+    max 10
+#end
+#root
+    box 0, 0, 150, 150
+    color 0xebdbb2
+    background 0x282828
+    axis x, y
+#end
+#legend
+    position "right"
+#end
+@polyline
+    points (0, 2), (2, 3), (3, 7), (5, 7.5), (6, 4), (8, 5), (10, 9)
+    color 0x928374
+    dash "3 3"
+    name "linear"
+#end
+@polyline
+    points (0, 2), (2, 3), (3, 7), (5, 7.5), (6, 4), (8, 5), (10, 9)
+    curve "monotone"
+    color 0xfabd2f
+    thickness 2
+    name "monotone"
+#end
+@polyline
+    points (0, 0.5), (2, 1), (4, 2.5), (6, 1.5), (8, 3), (10, 2)
+    curve "step-after"
+    color 0x83a598
+    name "step-after"
+#end
@@ -0,0 +1,36 @@
+#define x
+    max 10
+#end
+#define y
+    max 10
+#end
+#root
+    box 0, 0, 150, 150
+    color 0xebdbb2
+    background 0x282828
+    axis x, y
+#end
+#legend
+    position "right"
+#end
+@scatter
+    points (1, 2), (2, 3), (3, 5),
+        (4, 4), (5, 6)
+    points (6, 5)
+    color 0x83a598
+    name "measures"
+#end
+@scatter
+    points (2, 7), (4, 8), (6, 9), (8, 8)
+    marker "cross"
+    size 6
+    color 0xfb4934
+    name "outliers"
+#end
+@scatter
+    points (7, 2), (8, 3), (9, 1)
+    marker "triangle"
+    alpha 0.6
+    color 0xfabd2f
+    name "controls"
+#end
@@ -0,0 +1,41 @@
+#define x
+    max 10
+#end
+#define y
+    max 10
+#end
+#root
+    box 0, 0, 150, 150
+    color 0xebdbb2
+    background 0x282828
+    axis x, y
+#end
+#legend
+#end
+$var fg 0xebdbb2
+@rect
+    from 1, 1
+    to 4, 3
+    fill 0x458588
+    alpha 0.5
+    color $fg
+    name "region"
+#end
+@circle
+    at 7, 7
+    radius 2
+    color 0xfabd2f
+    thickness 2
+    name "circle"
+#end
+@circle
+    at 7, 2
+    radius 2, 1
+    fill 0xb16286
+#end
+@polygon
+    points (1, 5), (4, 9), (5, 5)
+    fill 0x98971a
+    color $fg
+    name "triangle"
+#end
@@ -0,0 +1,41 @@
+#define x
+    max 10
+#end
+#define y
+    min -1.5
+    max 1.5
+#end
+#root
+    box 0, 0, 200, 100
+    color 0xebdbb2
+    background 0x282828
+    axis x, y
+#end
+@graph
+    func "sin(x)"
+    color 0xfabd2f
+#end
+@point
+    at 1.57, 1
+    color 0xfb4934
+#end
+@text
+    at 1.57, 1.1
+    content "maximum"
+    anchor "middle"
+    color 0xfb4934
+    size 8
+#end
+@text
+    at 5, 0.2
+    content "rising"
+    rotate 45
+    color 0xebdbb2
+    name "slope"
+#end
+@text
+    at 150, 12
+    content "pixel note"
+    space "pixel"
+    color 0x83a598
+#end
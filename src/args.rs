@@ -6,6 +6,10 @@ use clap:: {
 
 use clap_stdin::MaybeStdin;
 
+use std::path::PathBuf;
+
+use gcompiler::Format;
+
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
 pub struct QArgs {
@@ -26,10 +30,51 @@ pub enum Entity {
 pub struct CompileCommand {
     /// The path of the source file to compile
     pub path: String,
+
+    #[clap(flatten)]
+    pub output: OutputArgs,
 }
 
 #[derive(Debug, Args)]
 pub struct RawCommand {
     /// The input string to compile
     pub input: MaybeStdin<String>,
+
+    #[clap(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// The file to write the graph to, instead of the standard output
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// The format of the generated graph, inferred from the output file
+    /// extension when omitted
+    #[clap(short, long, value_enum)]
+    pub format: Option<Format>,
+
+    /// Scale factor of the png output, 1 being one pixel per svg unit
+    #[clap(long, default_value_t = 1.0, conflicts_with = "dpi")]
+    pub scale: f32,
+
+    /// Resolution of the png output, 96 dpi being a scale of 1
+    #[clap(long)]
+    pub dpi: Option<f32>,
+}
+
+impl OutputArgs {
+    pub fn format(&self) -> Format {
+        self.format
+            .or_else(|| self.output.as_deref().and_then(Format::from_path))
+            .unwrap_or(Format::Svg)
+    }
+
+    pub fn png_scale(&self) -> f32 {
+        match self.dpi {
+            Some(dpi) => dpi / 96.0,
+            None => self.scale,
+        }
+    }
 }
@@ -0,0 +1,259 @@
+use crate::interpreter::Declaration;
+use crate::transform::Scale;
+
+/// Font size of the tick labels when the axis doesn't set one
+pub const FONT_SIZE: f64 = 8.0;
+/// Length of the major ticks, the minor ones being half as long
+pub const TICK_LENGTH: f64 = 4.0;
+/// Space between a tick and its label
+pub const LABEL_GAP: f64 = 2.0;
+/// Font size of the axis title, relative to the one of the labels
+const TITLE_SCALE: f64 = 1.25;
+/// Average width of a character, relative to the font size
+const CHAR_WIDTH: f64 = 0.6;
+/// Number of major ticks aimed at when the axis doesn't set a step
+const TICK_COUNT: f64 = 5.0;
+/// Upper bound on the number of ticks of an axis
+pub const MAX_TICKS: f64 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tick {
+    pub value: f64,
+    pub major: bool,
+}
+
+/// How the tick labels are written, parsed from a pattern like `"{:.2}"`,
+/// `"{:e}"` or `"{} %"`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub prefix: String,
+    pub suffix: String,
+    /// number of decimals, guessed from the step when not given
+    pub precision: Option<usize>,
+    /// scientific notation
+    pub exponent: bool,
+}
+
+impl NumberFormat {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let start = pattern.find('{').ok_or("Missing '{}' where the number is written")?;
+        let end = pattern[start..].find('}').map(|end| start + end).ok_or("Missing '}' in the format")?;
+        let spec = &pattern[start + 1..end];
+        if pattern[end + 1..].contains('{') {
+            return Err(String::from("Only one number can be written"));
+        }
+
+        let mut format = NumberFormat {
+            prefix: pattern[..start].to_string(),
+            suffix: pattern[end + 1..].to_string(),
+            precision: None,
+            exponent: false,
+        };
+
+        let spec = match spec.strip_prefix(':') {
+            Some(spec) => spec,
+            None if spec.is_empty() => return Ok(format),
+            None => return Err(format!("Unknown format '{{{}}}'", spec)),
+        };
+        let spec = match spec.strip_suffix('e') {
+            Some(spec) => {
+                format.exponent = true;
+                spec
+            },
+            None => spec,
+        };
+        if let Some(precision) = spec.strip_prefix('.') {
+            format.precision = Some(precision.parse::<usize>().map_err(|_| format!("Invalid precision '{}'", precision))?);
+        } else if !spec.is_empty() {
+            return Err(format!("Unknown format '{{:{}}}'", spec));
+        }
+
+        Ok(format)
+    }
+
+    pub fn format(&self, value: f64, step: f64) -> String {
+        let number = match (self.exponent, self.precision) {
+            (true, Some(precision)) => format!("{:.*e}", precision, value),
+            (true, None) => format!("{:e}", value),
+            (false, precision) => format!("{:.*}", precision.unwrap_or(decimals(step)), value),
+        };
+        format!("{}{}{}", self.prefix, number, self.suffix)
+    }
+}
+
+/// Number of decimals needed to write the multiples of `step`
+fn decimals(step: f64) -> usize {
+    (0..10).find(|&decimals| {
+        let scaled = step * 10_f64.powi(decimals as i32);
+        (scaled - scaled.round()).abs() < 1e-6 * scaled.abs().max(1.0)
+    }).unwrap_or(10)
+}
+
+/// A round step, 1, 2 or 5 times a power of ten, giving about
+/// `TICK_COUNT` ticks over the range
+pub fn nice_step(min: f64, max: f64) -> f64 {
+    let raw = (max - min) / TICK_COUNT;
+    let magnitude = 10_f64.powf(raw.log10().floor());
+    let nice = match raw / magnitude {
+        n if n < 1.5 => 1.0,
+        n if n < 3.0 => 2.0,
+        n if n < 7.0 => 5.0,
+        _ => 10.0,
+    };
+    nice * magnitude
+}
+
+/// Step between the major ticks of an axis, in powers of the base on the
+/// log scales
+pub fn step(axis: &Declaration) -> f64 {
+    if axis.categories.is_some() {
+        return 1.0;
+    }
+    let scale = axis.scale;
+    let (min, max) = (scale.apply(axis.min.unwrap_or(0.0)), scale.apply(axis.max));
+    axis.step.unwrap_or_else(|| match scale {
+        Scale::Linear => nice_step(min, max),
+        // only the whole powers are round values
+        Scale::Log { .. } | Scale::Symlog { .. } => nice_step(min, max).ceil(),
+    })
+}
+
+/// Estimated number of ticks of an axis, before they are generated
+pub fn tick_count(axis: &Declaration) -> f64 {
+    let scale = axis.scale;
+    let (min, max) = (scale.apply(axis.min.unwrap_or(0.0)), scale.apply(axis.max));
+    (max - min) / step(axis) * (axis.minor.unwrap_or(0) + 1) as f64
+}
+
+/// The values of the major ticks, from the last one before the range of
+/// the axis to the first one after it
+fn majors(axis: &Declaration) -> Vec<f64> {
+    let min = axis.min.unwrap_or(0.0);
+    let step = step(axis);
+    let multiples = |min: f64, max: f64| {
+        let epsilon = step * 1e-9;
+        ((min + epsilon) / step).floor() as i64..=((max - epsilon) / step).ceil() as i64
+    };
+
+    if let Some(categories) = &axis.categories {
+        return (1..=categories.len()).map(|i| i as f64).collect();
+    }
+    match axis.scale {
+        Scale::Linear => multiples(min, axis.max).map(|i| i as f64 * step).collect(),
+        Scale::Log { base } => {
+            multiples(min.log(base), axis.max.log(base)).map(|i| base.powf(i as f64 * step)).collect()
+        },
+        // 0 and the powers of the base times the threshold on both sides
+        Scale::Symlog { base, threshold } => {
+            let powers = |bound: f64| {
+                let last = ((bound / threshold).log(base).max(0.0) / step).ceil() as i64;
+                (0..=last).map(move |i| threshold * base.powf(i as f64 * step))
+            };
+            let mut values: Vec<f64> = Vec::new();
+            if min < 0.0 {
+                values.extend(powers(-min).map(|value| -value));
+                values.reverse();
+            }
+            values.push(0.0);
+            if axis.max > 0.0 {
+                values.extend(powers(axis.max));
+            }
+            values
+        },
+    }
+}
+
+/// The major ticks in the range of the axis, and `minor` ticks evenly
+/// spaced between two of them
+pub fn ticks(axis: &Declaration) -> Vec<Tick> {
+    let min = axis.min.unwrap_or(0.0);
+    let divisions = axis.minor.unwrap_or(0) + 1;
+    // tolerance so the bounds of the range get their ticks
+    let epsilon = (axis.max - min) * 1e-9;
+
+    let majors = majors(axis);
+    let mut ticks: Vec<Tick> = Vec::new();
+    for pair in majors.windows(2) {
+        ticks.extend((0..divisions).map(|i| Tick {
+            value: pair[0] + (pair[1] - pair[0]) * i as f64 / divisions as f64,
+            major: i == 0,
+        }));
+    }
+    ticks.extend(majors.last().map(|&value| Tick { value, major: true }));
+    ticks.retain(|tick| tick.value >= min - epsilon && tick.value <= axis.max + epsilon);
+    ticks
+}
+
+/// The labels of the major ticks, `-0` being written `0`, or the names of
+/// the categories
+pub fn labels(axis: &Declaration) -> Vec<(f64, String)> {
+    if let Some(categories) = &axis.categories {
+        return categories.iter().enumerate().map(|(i, category)| ((i + 1) as f64, category.clone())).collect();
+    }
+    let step = step(axis);
+    let format = axis.format.clone().unwrap_or_default();
+    ticks(axis).iter().filter(|tick| tick.major).map(|tick| match axis.scale {
+        Scale::Linear => {
+            let value = if tick.value.abs() < step * 1e-9 { 0.0 } else { tick.value };
+            (value, format.format(value, step))
+        },
+        // the decimals of a power depend on the power itself
+        Scale::Log { .. } | Scale::Symlog { .. } => (tick.value, format.format(tick.value, tick.value)),
+    }).collect()
+}
+
+pub fn font_size(axis: &Declaration) -> f64 {
+    axis.fontsize.unwrap_or(FONT_SIZE)
+}
+
+pub fn title_size(axis: &Declaration) -> f64 {
+    font_size(axis) * TITLE_SCALE
+}
+
+/// Estimated width of a text, fonts not being available when compiling
+pub fn text_width(text: &str, font_size: f64) -> f64 {
+    text.chars().count() as f64 * font_size * CHAR_WIDTH
+}
+
+/// Width of the widest label of the axis
+pub fn labels_width(axis: &Declaration) -> f64 {
+    let font_size = font_size(axis);
+    labels(axis).iter().map(|(_, label)| text_width(label, font_size)).fold(0.0, f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_format_parses_patterns() {
+        assert_eq!(NumberFormat::parse("{}"), Ok(NumberFormat::default()));
+        let format = NumberFormat::parse("~{:.2} %").unwrap();
+        assert_eq!((format.prefix.as_str(), format.suffix.as_str()), ("~", " %"));
+        assert_eq!(format.precision, Some(2));
+        assert!(!format.exponent);
+
+        let format = NumberFormat::parse("{:.1e}").unwrap();
+        assert_eq!(format.precision, Some(1));
+        assert!(format.exponent);
+    }
+
+    #[test]
+    fn number_format_rejects_invalid_patterns() {
+        assert!(NumberFormat::parse("no number").is_err());
+        assert!(NumberFormat::parse("{:.2").is_err());
+        assert!(NumberFormat::parse("{} and {}").is_err());
+        assert!(NumberFormat::parse("{x}").is_err());
+        assert!(NumberFormat::parse("{:.x}").is_err());
+        assert!(NumberFormat::parse("{:b}").is_err());
+    }
+
+    #[test]
+    fn number_format_writes_decimals_of_the_step() {
+        let format = NumberFormat::default();
+        assert_eq!(format.format(2.0, 0.5), "2.0");
+        assert_eq!(format.format(3.0, 1.0), "3");
+        assert_eq!(NumberFormat::parse("{:.2} m").unwrap().format(1.5, 0.5), "1.50 m");
+        assert_eq!(NumberFormat::parse("{:e}").unwrap().format(1500.0, 1.0), "1.5e3");
+    }
+}
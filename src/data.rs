@@ -0,0 +1,124 @@
+use crate::diagnostic::{Code, Diagnostic};
+
+use std::path::Path;
+
+/// A column of a csv file, by the name in its header or by its position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Name(String),
+    /// position of the column, the first one being 1
+    Index(usize),
+}
+
+impl Column {
+    /// Position of the column in a row, from 0
+    fn find(&self, headers: Option<&csv::StringRecord>, path: &str) -> Result<usize, Diagnostic> {
+        match (self, headers) {
+            (Column::Index(index), _) => Ok(index - 1),
+            (Column::Name(name), Some(headers)) => headers.iter().position(|header| header == name).ok_or(
+                Diagnostic::error(Code::Undefined, format!("Unknown column '{}' in '{}'", name, path))
+                    .with_note(format!("The columns are: {}", headers.iter().collect::<Vec<_>>().join(", ")))
+            ),
+            (Column::Name(name), None) => Err(
+                Diagnostic::error(Code::Undefined, format!("Unknown column '{}' in '{}'", name, path))
+                    .with_note("The file has no header naming its columns")
+                    .with_help("Select the columns by position, like 'columns 1, 2'")
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Column::Name(name) => write!(f, "'{}'", name),
+            Column::Index(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+/// Reads the values of `columns` in every row of a csv file, the first row
+/// naming the columns when `header` is set
+pub fn read(path: &Path, columns: &[Column], header: bool) -> Result<Vec<Vec<f64>>, Diagnostic> {
+    let display = path.display().to_string();
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(header)
+        // rows too short are reported with the columns they miss
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|e| Diagnostic::error(Code::Data, format!("Cannot read '{}' -> {}", display, e)))?;
+
+    let headers = match header {
+        true => Some(reader.headers()
+            .map_err(|e| Diagnostic::error(Code::Data, format!("Cannot read '{}' -> {}", display, e)))?
+            .clone()),
+        false => None,
+    };
+    let indices = columns.iter().map(|column| column.find(headers.as_ref(), &display)).collect::<Result<Vec<usize>, Diagnostic>>()?;
+
+    let mut rows: Vec<Vec<f64>> = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| Diagnostic::error(Code::Data, format!("Cannot read '{}' -> {}", display, e)))?;
+        let line = record.position().map_or(0, |position| position.line());
+        let value = |index: usize, column: &Column| {
+            let cell = record.get(index).ok_or(
+                Diagnostic::error(Code::Data, format!("Missing column {} at line {} of '{}'", column, line, display))
+                    .with_note(format!("The row has {} columns", record.len()))
+            )?;
+            cell.parse::<f64>().map_err(|_| {
+                Diagnostic::error(Code::Data, format!("Invalid number '{}' at line {} of '{}'", cell, line, display))
+                    .with_note(format!("Found in column {}", column))
+            })
+        };
+        rows.push(indices.iter().zip(columns).map(|(index, column)| value(*index, column)).collect::<Result<Vec<f64>, Diagnostic>>()?);
+    }
+
+    if rows.is_empty() {
+        return Err(Diagnostic::error(Code::Data, format!("No rows in '{}'", display)));
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads `content` as a csv file named after the test
+    fn read_csv(name: &str, content: &str, columns: &[Column], header: bool) -> Result<Vec<Vec<f64>>, Diagnostic> {
+        let path = std::env::temp_dir().join(format!("gcompiler-{}-{}.csv", name, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        let rows = read(&path, columns, header);
+        std::fs::remove_file(&path).unwrap();
+        rows
+    }
+
+    #[test]
+    fn reads_columns_by_name_and_position() {
+        let columns = [Column::Name(String::from("y")), Column::Index(1)];
+        let rows = read_csv("columns", "x, y\n1, 2\n3.5, -4\n", &columns, true).unwrap();
+        assert_eq!(rows, [[2.0, 1.0], [-4.0, 3.5]]);
+    }
+
+    #[test]
+    fn reports_the_line_of_invalid_rows() {
+        let columns = [Column::Index(1), Column::Index(2)];
+        let error = read_csv("invalid", "1, 2\n3, four\n", &columns, false).unwrap_err();
+        assert_eq!(error.code, Code::Data);
+        assert!(error.message.starts_with("Invalid number 'four' at line 2"));
+
+        let error = read_csv("short", "1, 2\n3\n", &columns, false).unwrap_err();
+        assert!(error.message.starts_with("Missing column 2 at line 2"));
+        assert_eq!(error.notes, ["The row has 1 columns"]);
+    }
+
+    #[test]
+    fn reports_unknown_columns_and_empty_files() {
+        let error = read_csv("unknown", "x, y\n1, 2\n", &[Column::Name(String::from("z"))], true).unwrap_err();
+        assert_eq!(error.code, Code::Undefined);
+        assert_eq!(error.notes, ["The columns are: x, y"]);
+
+        let error = read_csv("empty", "x, y\n", &[Column::Index(1)], true).unwrap_err();
+        assert!(error.message.starts_with("No rows"));
+    }
+}
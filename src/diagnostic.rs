@@ -0,0 +1,169 @@
+use crate::parser::{Span, Token};
+
+use std::fmt;
+
+/// Error codes, so an error can be recognized independently of its wording
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Code {
+    /// A word or a character the lexer doesn't know
+    UnknownWord,
+    /// A malformed number or hexadecimal value
+    InvalidLiteral,
+    /// A token that isn't allowed at its position
+    UnexpectedToken,
+    /// A keyword or a directive without its value
+    MissingValue,
+    /// A tuple with the wrong number of values
+    TupleArity,
+    /// A declaration or a function that isn't closed by `#end`
+    MissingEnd,
+    /// A required keyword or declaration that isn't given
+    MissingKeyword,
+    /// A value outside of its allowed range
+    InvalidValue,
+    /// A reference to something that isn't defined
+    Undefined,
+    /// Something that is defined twice
+    Redefined,
+    /// A mathematical expression that can't be parsed or evaluated
+    InvalidExpression,
+    /// A malformed compile-time directive
+    InvalidDirective,
+    /// The graph can't be rendered or written
+    Output,
+    /// A data file that can't be read or holds invalid values
+    Data,
+}
+
+impl Code {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::UnknownWord => "E0001",
+            Code::InvalidLiteral => "E0002",
+            Code::UnexpectedToken => "E0003",
+            Code::MissingValue => "E0004",
+            Code::TupleArity => "E0005",
+            Code::MissingEnd => "E0006",
+            Code::MissingKeyword => "E0007",
+            Code::InvalidValue => "E0008",
+            Code::Undefined => "E0009",
+            Code::Redefined => "E0010",
+            Code::InvalidExpression => "E0011",
+            Code::InvalidDirective => "E0012",
+            Code::Output => "E0013",
+            Code::Data => "E0014",
+        }
+    }
+}
+
+/// Where a diagnostic points in the source, lines and columns starting at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    pub span: Span,
+}
+
+/// An error found while compiling a .g program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub message: String,
+    pub location: Option<Location>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, message: impl Into<String>) -> Self {
+        Diagnostic {
+            code,
+            message: message.into(),
+            location: None,
+            notes: Vec::new(),
+            help: Vec::new(),
+        }
+    }
+
+    /// Points the diagnostic at a token of the source
+    pub fn at(mut self, token: &Token) -> Self {
+        self.location = Some(Location {
+            line: token.line,
+            column: token.column,
+            span: token.span,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    /// Renders the diagnostic with the snippet of `source` it points at,
+    /// `path` being the name under which the source is displayed
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let mut rendered = format!("error[{}]: {}\n", self.code.as_str(), self.message);
+        let gutter = self.location.map_or(1, |location| location.line.to_string().len());
+        let pad = " ".repeat(gutter);
+
+        match self.location {
+            Some(location) => {
+                rendered.push_str(&format!("{}--> {}:{}:{}\n", pad, path, location.line, location.column));
+            },
+            None => {
+                rendered.push_str(&format!("{}--> {}\n", pad, path));
+            },
+        }
+
+        if let Some(location) = self.location {
+            if let Some(text) = source.lines().nth(location.line.saturating_sub(1)) {
+                // tabs are kept so the carets line up with the source
+                let indent: String = text.chars()
+                    .take(location.column.saturating_sub(1))
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let available = text.chars().count().saturating_sub(location.column.saturating_sub(1)).max(1);
+                let width = source.get(location.span.start..location.span.end)
+                    .map_or(1, |s| s.chars().count())
+                    .clamp(1, available);
+
+                rendered.push_str(&format!("{} |\n", pad));
+                rendered.push_str(&format!("{} | {}\n", location.line, text));
+                rendered.push_str(&format!("{} | {}{}\n", pad, indent, "^".repeat(width)));
+            }
+        }
+
+        for note in self.notes.iter() {
+            rendered.push_str(&format!("{} = note: {}\n", pad, note));
+        }
+        for help in self.help.iter() {
+            rendered.push_str(&format!("{} = help: {}\n", pad, help));
+        }
+
+        rendered
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error[{}]: {}", self.code.as_str(), self.message)?;
+        if let Some(location) = self.location {
+            write!(f, " at line {}, column {}", location.line, location.column)?;
+        }
+        for note in self.notes.iter() {
+            write!(f, "\n  = note: {}", note)?;
+        }
+        for help in self.help.iter() {
+            write!(f, "\n  = help: {}", help)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
@@ -0,0 +1,594 @@
+use crate::diagnostic::{Code, Diagnostic};
+use crate::parser::{Token, TokenType};
+
+use exmex::prelude::*;
+
+/// Token types that can be bound to a compile-time variable
+const BINDABLE_TOKENS: [TokenType; 5] = [
+    TokenType::STRING,
+    TokenType::INTERGER,
+    TokenType::FLOAT,
+    TokenType::HEX,
+    TokenType::BOOLEAN,
+];
+
+/// Upper bound on the number of iterations of a single `$for` loop
+const MAX_ITERATIONS: usize = 100_000;
+
+/// A compile-time variable declared with `$var name value`
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name: String,
+    pub value: Vec<Token>,
+    pub line: usize,
+}
+
+/// Formats a value computed at compile time the way it would be written
+/// in a .g file, integers without a trailing `.0`
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+/// The first clause of an exmex error, the rest being a debug dump of
+/// its internal operators
+pub(crate) fn expression_error(error: &exmex::ExError) -> &str {
+    error.msg().split(", ").next().unwrap_or_default()
+}
+
+/// Resolves the compile-time directives (`$var`, `$for`), substitutes
+/// every `$name` reference by the tokens it is bound to and evaluates the
+/// interpolated strings, so the interpreter only ever sees plain literals.
+pub struct Expander {
+    tokens: Vec<Token>,
+    position: usize,
+    bindings: Vec<Binding>,
+    /// variables of the loops being unrolled, the only words besides
+    /// keywords allowed in their bodies
+    loop_variables: Vec<String>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Expander {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Expander {
+            tokens,
+            position: 0,
+            bindings: Vec::new(),
+            loop_variables: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.position += len;
+    }
+
+    fn next(&self) -> Option<Token> {
+        self.tokens.get(self.position).cloned()
+    }
+
+    fn get_binding(&self, name: &str) -> Option<&Binding> {
+        self.bindings.iter().find(|binding| binding.name == name)
+    }
+
+    /// Returns the tokens bound to a `$name` reference, placed on the
+    /// location of the reference
+    fn resolve(&self, token: &Token) -> Result<Vec<Token>, Diagnostic> {
+        match self.get_binding(&token.value) {
+            Some(binding) => Ok(
+                binding.value.iter().map(|t| Token {
+                    line: token.line,
+                    column: token.column,
+                    span: token.span,
+                    ..t.clone()
+                }).collect()
+            ),
+            None => Err(Diagnostic::error(Code::Undefined, format!("Undefined compile-time variable '${}'", token.value)).at(token)),
+        }
+    }
+
+    /// Returns the value of a loop variable referenced without `$`, any
+    /// other word being an unknown keyword
+    fn resolve_identifier(&self, token: &Token) -> Result<Vec<Token>, Diagnostic> {
+        if !self.loop_variables.contains(&token.value) {
+            return Err(Diagnostic::error(Code::UnknownWord, format!("Unknown keyword '{}'", token.value)).at(token));
+        }
+        self.resolve(token)
+    }
+
+    /// Evaluates the content of a `{...}` segment, either a variable or an
+    /// arithmetic expression over numeric variables
+    fn evaluate(&self, expression: &str, directive: &Token) -> Result<String, Diagnostic> {
+        let expression = expression.trim().replace('$', "");
+        if expression.is_empty() {
+            return Err(Diagnostic::error(Code::MissingValue, "Empty '{}' in interpolated string").at(directive));
+        }
+
+        if let Some(binding) = self.get_binding(&expression) {
+            let value = binding.value.iter().map(|t| match t.token_type {
+                TokenType::HEX => format!("0x{}", t.value),
+                TokenType::SYMBOL => format!("{} ", t.value),
+                _ => t.value.clone(),
+            }).collect::<String>();
+            return Ok(value);
+        }
+
+        let expr = exmex::parse::<f64>(&expression).map_err(|e| Diagnostic::error(Code::InvalidExpression, 
+            format!("Cannot evaluate '{{{}}}' -> {}", expression, expression_error(&e))
+        ).at(directive))?;
+
+        let mut values: Vec<f64> = Vec::new();
+        for name in expr.var_names() {
+            let binding = self.get_binding(name).ok_or(Diagnostic::error(Code::Undefined, 
+                format!("Undefined compile-time variable '{}' in interpolated string", name)
+            ).at(directive))?;
+            let is_number = binding.value.len() == 1
+                && (binding.value[0].token_type == TokenType::INTERGER || binding.value[0].token_type == TokenType::FLOAT);
+            if !is_number {
+                return Err(Diagnostic::error(Code::InvalidExpression, format!("Compile-time variable '{}' can't be used in an arithmetic expression", name))
+                    .at(directive)
+                    .with_note("Expected a single number"));
+            }
+            values.push(binding.value[0].value.parse::<f64>().unwrap());
+        }
+
+        let value = expr.eval(&values).map_err(|e| Diagnostic::error(Code::InvalidExpression, 
+            format!("Cannot evaluate '{{{}}}' -> {}", expression, expression_error(&e))
+        ).at(directive))?;
+        Ok(format_number(value))
+    }
+
+    /// Turns an interpolated string `."L{i}"` into a plain string, `{{`
+    /// and `}}` being escaped braces
+    fn interpolate(&self, token: &Token) -> Result<Token, Diagnostic> {
+        let mut string = String::new();
+        let mut chars = token.value.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    string.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    string.push('}');
+                },
+                '{' => {
+                    let mut expression = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => expression.push(c),
+                            None => {
+                                return Err(Diagnostic::error(Code::InvalidLiteral, "Missing '}' in interpolated string").at(token));
+                            },
+                        }
+                    }
+                    string.push_str(&self.evaluate(&expression, token)?);
+                },
+                '}' => {
+                    return Err(Diagnostic::error(Code::InvalidLiteral, "Unmatched '}' in interpolated string")
+                        .at(token)
+                        .with_note("Use '}}' for a literal brace"));
+                },
+                _ => string.push(c),
+            }
+        }
+
+        Ok(Token {
+            token_type: TokenType::STRING,
+            value: string,
+            ..token.clone()
+        })
+    }
+
+    /// Reads a single value of a `$var` binding, resolving references
+    /// to previously declared variables
+    fn get_value(&mut self, varname: &str, directive: &Token) -> Result<Vec<Token>, Diagnostic> {
+        let current_token = self.next();
+        if current_token.is_none() {
+            return Err(Diagnostic::error(Code::MissingValue, format!("Missing value for compile-time variable '{}'", varname)).at(directive));
+        }
+
+        let token = current_token.unwrap();
+        self.consume(1);
+        if token.token_type == TokenType::COMPVAR {
+            return self.resolve(&token);
+        }
+        if token.token_type == TokenType::IDENTIFIER {
+            return self.resolve_identifier(&token);
+        }
+        if token.token_type == TokenType::FSTRING {
+            return Ok(vec![self.interpolate(&token)?]);
+        }
+
+        if !BINDABLE_TOKENS.contains(&token.token_type) {
+            return Err(Diagnostic::error(Code::UnexpectedToken, format!("Unexpected token '{}'", token.value))
+                .at(&token)
+                .with_note("Expected a string, a number, a hexadecimal value, a boolean or a tuple of them"));
+        }
+
+        Ok(vec![token])
+    }
+
+    fn process_var(&mut self) -> Result<(), Diagnostic> {
+        let directive = self.next().unwrap();
+        self.consume(1);
+
+        let current_token = self.next();
+        if current_token.is_none() || current_token.as_ref().unwrap().token_type != TokenType::VARNAME {
+            return Err(Diagnostic::error(Code::MissingValue, "Missing variable name after '$var'").at(&directive));
+        }
+
+        let varname = current_token.unwrap().value;
+        if let Some(binding) = self.get_binding(&varname) {
+            return Err(Diagnostic::error(Code::Redefined, format!("Compile-time variable '${}' is already defined", varname))
+                .at(&directive)
+                .with_note(format!("First defined at line {}", binding.line)));
+        }
+        self.consume(1);
+
+        let mut value = self.get_value(&varname, &directive)?;
+        while let Some(token) = self.next() {
+            if token.token_type != TokenType::SYMBOL || token.value != "," {
+                break;
+            }
+            value.push(token);
+            self.consume(1);
+            value.append(&mut self.get_value(&varname, &directive)?);
+        }
+
+        self.bindings.push(Binding {
+            name: varname,
+            value,
+            line: directive.line,
+        });
+
+        Ok(())
+    }
+
+    fn expect_symbol(&mut self, symbol: &str, directive: &Token) -> Result<(), Diagnostic> {
+        match self.next() {
+            Some(token) if token.token_type == TokenType::SYMBOL && token.value == symbol => {
+                self.consume(1);
+                Ok(())
+            },
+            Some(token) => Err(Diagnostic::error(Code::UnexpectedToken, format!("Unexpected token '{}'", token.value))
+                .at(&token)
+                .with_note(format!("Expected '{}' in the '$for' loop", symbol))),
+            None => Err(Diagnostic::error(Code::InvalidDirective, format!("Missing '{}' in the '$for' loop", symbol)).at(directive)),
+        }
+    }
+
+    /// Reads a range bound or a step of a `$for` loop, which is either a
+    /// number or a variable bound to a single number
+    fn get_bound(&mut self, directive: &Token) -> Result<Token, Diagnostic> {
+        let current_token = self.next();
+        if current_token.is_none() {
+            return Err(Diagnostic::error(Code::InvalidDirective, "Missing range in the '$for' loop").at(directive));
+        }
+
+        let mut token = current_token.unwrap();
+        self.consume(1);
+        if token.token_type == TokenType::COMPVAR || token.token_type == TokenType::IDENTIFIER {
+            let value = match token.token_type {
+                TokenType::COMPVAR => self.resolve(&token)?,
+                _ => self.resolve_identifier(&token)?,
+            };
+            if value.len() != 1 {
+                return Err(Diagnostic::error(Code::InvalidDirective, format!("Compile-time variable '${}' can't be used as a range bound", token.value))
+                    .at(&token)
+                    .with_note("Expected a single number, found a tuple"));
+            }
+            token = value[0].clone();
+        }
+
+        if token.token_type != TokenType::INTERGER && token.token_type != TokenType::FLOAT {
+            return Err(Diagnostic::error(Code::UnexpectedToken, format!("Unexpected token '{}'", token.value))
+                .at(&token)
+                .with_note("Expected an integer or a float"));
+        }
+
+        Ok(token)
+    }
+
+    /// Collects the tokens between the parentheses of a `$for` loop body
+    fn get_body(&mut self, directive: &Token) -> Result<Vec<Token>, Diagnostic> {
+        let mut body: Vec<Token> = Vec::new();
+        let mut depth = 1;
+        while let Some(token) = self.next() {
+            self.consume(1);
+            if token.token_type == TokenType::SYMBOL && token.value == "(" {
+                depth += 1;
+            }
+            if token.token_type == TokenType::SYMBOL && token.value == ")" {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(body);
+                }
+            }
+            body.push(token);
+        }
+
+        Err(Diagnostic::error(Code::InvalidDirective, "Missing ')' to close the '$for' loop").at(directive))
+    }
+
+    /// Unrolls `$for start..end [step n] | i -> ( ... )`, the end being
+    /// excluded unless the range is written `start..=end`
+    fn process_for(&mut self) -> Result<Vec<Token>, Diagnostic> {
+        let directive = self.next().unwrap();
+        self.consume(1);
+        let line = directive.line;
+
+        let start = self.get_bound(&directive)?;
+        let inclusive = match self.next() {
+            Some(token) if token.token_type == TokenType::SYMBOL && (token.value == ".." || token.value == "..=") => {
+                self.consume(1);
+                token.value == "..="
+            },
+            Some(token) => {
+                return Err(Diagnostic::error(Code::UnexpectedToken, format!("Unexpected token '{}'", token.value))
+                    .at(&token)
+                    .with_note("Expected a range like '1..5' or '1..=5'"));
+            },
+            None => {
+                return Err(Diagnostic::error(Code::InvalidDirective, "Missing range in the '$for' loop").at(&directive));
+            },
+        };
+        let end = self.get_bound(&directive)?;
+
+        let mut step = None;
+        if let Some(token) = self.next() {
+            if token.token_type == TokenType::KEYWORD && token.value == "step" {
+                self.consume(1);
+                step = Some(self.get_bound(&directive)?);
+            }
+        }
+
+        self.expect_symbol("|", &directive)?;
+        let current_token = self.next();
+        if current_token.is_none() || current_token.as_ref().unwrap().token_type != TokenType::VARNAME {
+            return Err(Diagnostic::error(Code::MissingValue, "Missing loop variable name in the '$for' loop").at(&directive));
+        }
+        let varname = current_token.unwrap().value;
+        if let Some(binding) = self.get_binding(&varname) {
+            return Err(Diagnostic::error(Code::Redefined, format!("Loop variable '{}' shadows a compile-time variable", varname))
+                .at(&directive)
+                .with_note(format!("'{}' is defined at line {}", varname, binding.line)));
+        }
+        self.consume(1);
+        self.expect_symbol("->", &directive)?;
+        self.expect_symbol("(", &directive)?;
+        let body = self.get_body(&directive)?;
+
+        let is_integer = start.token_type == TokenType::INTERGER
+            && end.token_type == TokenType::INTERGER
+            && step.as_ref().is_none_or(|s| s.token_type == TokenType::INTERGER);
+        let start_value = start.value.parse::<f64>().unwrap();
+        let end_value = end.value.parse::<f64>().unwrap();
+        // the values are written with as many decimals as the start and the step
+        let precision = [Some(&start), step.as_ref()].into_iter().flatten()
+            .map(|token| token.value.split_once('.').map_or(0, |(_, decimals)| decimals.len()))
+            .max()
+            .unwrap_or(0);
+        let step_value = match step {
+            Some(step) => step.value.parse::<f64>().unwrap(),
+            None if start_value <= end_value => 1.0,
+            None => -1.0,
+        };
+
+        if step_value == 0.0 {
+            return Err(Diagnostic::error(Code::InvalidValue, "The step of the '$for' loop can't be 0").at(&directive));
+        }
+        if (end_value - start_value) * step_value < 0.0 {
+            return Err(Diagnostic::error(Code::InvalidValue, format!("The '$for' loop never reaches {} from {}", end.value, start.value))
+                .at(&directive)
+                .with_note(format!("The step {} goes the other way", step_value)));
+        }
+
+        let mut expanded: Vec<Token> = Vec::new();
+        // tolerance so float ranges like 0..=1 step 0.1 include their end
+        let epsilon = step_value.abs() * 1e-9;
+        let mut iteration = 0;
+        loop {
+            let value = start_value + iteration as f64 * step_value;
+            let distance = (end_value - value) * step_value.signum();
+            if distance < -epsilon || (!inclusive && distance <= epsilon) {
+                break;
+            }
+            if iteration >= MAX_ITERATIONS {
+                return Err(Diagnostic::error(Code::InvalidValue, format!("The '$for' loop exceeds {} iterations", MAX_ITERATIONS)).at(&directive));
+            }
+
+            let token = if is_integer {
+                Token { token_type: TokenType::INTERGER, value: format!("{}", value as i64), ..directive.clone() }
+            } else {
+                let scale = 10_f64.powi(precision as i32);
+                Token { token_type: TokenType::FLOAT, value: format_number((value * scale).round() / scale), ..directive.clone() }
+            };
+
+            let mut bindings = self.bindings.clone();
+            bindings.push(Binding {
+                name: varname.clone(),
+                value: vec![token],
+                line,
+            });
+            let mut loop_variables = self.loop_variables.clone();
+            loop_variables.push(varname.clone());
+            let mut expander = Expander {
+                tokens: body.clone(),
+                position: 0,
+                bindings,
+                loop_variables,
+                errors: Vec::new(),
+            };
+            let (mut tokens, errors) = expander.expand();
+            expanded.append(&mut tokens);
+            // the other iterations would only report the same errors
+            if !errors.is_empty() {
+                self.errors.extend(errors);
+                break;
+            }
+            iteration += 1;
+        }
+
+        Ok(expanded)
+    }
+
+    /// Skips what is left of a directive that failed to be expanded,
+    /// stopping before the next directive or block, a loop body between
+    /// parentheses being skipped up to its closing `)`
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.next() {
+            match token.token_type {
+                TokenType::COMPTIME | TokenType::DECLARATION | TokenType::FUNCTION | TokenType::DEFINE if depth == 0 => return,
+                TokenType::SYMBOL if token.value == "(" => depth += 1,
+                TokenType::SYMBOL if token.value == ")" => {
+                    self.consume(1);
+                    depth -= 1;
+                    if depth <= 0 {
+                        return;
+                    }
+                    continue;
+                },
+                _ => {},
+            }
+            self.consume(1);
+        }
+    }
+
+    /// Expands the tokens, going on after an error so that every faulty
+    /// directive and reference is reported, along with what could be
+    /// expanded
+    pub fn expand(&mut self) -> (Vec<Token>, Vec<Diagnostic>) {
+        let mut expanded: Vec<Token> = Vec::new();
+        let mut current_token = self.next();
+        while current_token.is_some() {
+            let token = current_token.unwrap();
+            let processed = match token.token_type {
+                TokenType::COMPTIME => {
+                    let processed = match token.value.as_str() {
+                        "var" => self.process_var(),
+                        "for" => self.process_for().map(|mut tokens| expanded.append(&mut tokens)),
+                        _ => {
+                            self.consume(1);
+                            Err(Diagnostic::error(Code::UnknownWord, format!("Unknown compile-time directive '${}'", token.value)).at(&token))
+                        },
+                    };
+                    if processed.is_err() {
+                        self.synchronize();
+                    }
+                    processed
+                },
+                TokenType::COMPVAR => {
+                    self.consume(1);
+                    self.resolve(&token).map(|mut tokens| expanded.append(&mut tokens))
+                },
+                TokenType::IDENTIFIER => {
+                    self.consume(1);
+                    self.resolve_identifier(&token).map(|mut tokens| expanded.append(&mut tokens))
+                },
+                TokenType::FSTRING => {
+                    self.consume(1);
+                    self.interpolate(&token).map(|token| expanded.push(token))
+                },
+                _ => {
+                    expanded.push(token);
+                    self.consume(1);
+                    Ok(())
+                },
+            };
+
+            if let Err(e) = processed {
+                self.errors.push(e);
+            }
+            current_token = self.next();
+        }
+
+        (expanded, std::mem::take(&mut self.errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Lexer;
+
+    fn expand(source: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        let (tokens, errors) = Lexer::new(source.to_string()).tokenize();
+        assert!(errors.is_empty());
+        Expander::new(tokens).expand()
+    }
+
+    /// The values of the tokens, the structure of a program aside
+    fn values(tokens: &[Token]) -> Vec<&str> {
+        tokens.iter().filter(|token| token.token_type != TokenType::SYMBOL).map(|token| token.value.as_str()).collect()
+    }
+
+    #[test]
+    fn var_is_substituted_by_its_tokens() {
+        let (tokens, errors) = expand("$var size 2\n$var pair 1, $size\n@point\n    at $pair\n#end");
+        assert!(errors.is_empty());
+        assert_eq!(values(&tokens), ["point", "at", "1", "2", "end"]);
+        assert_eq!(tokens[2].token_type, TokenType::INTERGER);
+    }
+
+    #[test]
+    fn var_cannot_be_redefined() {
+        let (_, errors) = expand("$var a 1\n$var a 2");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Code::Redefined);
+    }
+
+    #[test]
+    fn for_unrolls_its_body() {
+        let (tokens, errors) = expand("$for 1..3 | i -> (\n@point\n    at i, .\"{i}\"\n#end\n)");
+        assert!(errors.is_empty());
+        assert_eq!(values(&tokens), ["point", "at", "1", "1", "end", "point", "at", "2", "2", "end"]);
+        assert_eq!(tokens[4].token_type, TokenType::STRING);
+    }
+
+    #[test]
+    fn for_ranges_with_steps() {
+        let (tokens, _) = expand("$for 0..=1 step 0.5 | v -> ( $v )");
+        assert_eq!(values(&tokens), ["0", "0.5", "1"]);
+        let (tokens, _) = expand("$for 0..=0.5 step 0.1 | v -> ( v )");
+        assert_eq!(values(&tokens), ["0", "0.1", "0.2", "0.3", "0.4", "0.5"]);
+        let (tokens, _) = expand("$for 1.25..2 step 0.25 | v -> ( v )");
+        assert_eq!(values(&tokens), ["1.25", "1.5", "1.75"]);
+        let (tokens, _) = expand("$for 3..0 | v -> ( v )");
+        assert_eq!(values(&tokens), ["3", "2", "1"]);
+
+        let (_, errors) = expand("$for 0..3 step -1 | v -> ( v )");
+        assert_eq!(errors[0].code, Code::InvalidValue);
+        let (_, errors) = expand("$for 0..3 step 0 | v -> ( v )");
+        assert_eq!(errors[0].code, Code::InvalidValue);
+    }
+
+    #[test]
+    fn interpolation_evaluates_expressions() {
+        let (tokens, errors) = expand("$var n 2\n$var name \"sine\"\n.\"{name}: {n * 3 - 0.5} {{n}}\"");
+        assert!(errors.is_empty());
+        assert_eq!(tokens[0].token_type, TokenType::STRING);
+        assert_eq!(tokens[0].value, "sine: 5.5 {n}");
+
+        let (_, errors) = expand(".\"{1 +* 2}\"");
+        assert_eq!(errors[0].message, "Cannot evaluate '{1 +* 2}' -> a binary operator cannot be on the right another operator");
+    }
+
+    #[test]
+    fn loop_variable_is_scoped_to_its_body() {
+        let (tokens, errors) = expand("$for 1..3 | t -> ( )\n#define x\n#define t\n#root\n    axis x, t\n#end");
+        assert!(errors.is_empty());
+        let axis = tokens.iter().rev().find(|token| token.value == "t").unwrap();
+        assert_eq!(axis.token_type, TokenType::VAR);
+
+        let (_, errors) = expand("$for 1..3 | t -> ( )\n@point\n    at (t, 1)\n#end");
+        assert_eq!(errors[0].message, "Unknown keyword 't'");
+    }
+}
@@ -0,0 +1,148 @@
+/// Upper bound on the number of bins of a histogram
+pub const MAX_BINS: f64 = 1000.0;
+
+/// How the values of a histogram are split into bins of the same width
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binning {
+    Count(usize),
+    /// bins starting at a multiple of the width
+    Width(f64),
+    /// log2(n) + 1 bins for n values
+    Sturges,
+    /// bins twice as wide as the interquartile range over the cube root of
+    /// the number of values, Sturges being used when the range is empty
+    FreedmanDiaconis,
+}
+
+impl Binning {
+    pub const NAMES: [&'static str; 2] = ["sturges", "fd"];
+
+    pub fn parse(name: &str) -> Option<Binning> {
+        match name {
+            "sturges" => Some(Binning::Sturges),
+            "fd" => Some(Binning::FreedmanDiaconis),
+            _ => None,
+        }
+    }
+}
+
+/// The number of values in each bin, the first one starting at `start`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bins {
+    pub start: f64,
+    pub width: f64,
+    pub counts: Vec<usize>,
+}
+
+impl Bins {
+    /// The center and height of each bin, the heights being normalized so
+    /// the bins have an area of 1 when `density` is set
+    pub fn bars(&self, density: bool) -> Vec<(f64, f64)> {
+        let total = self.counts.iter().sum::<usize>() as f64;
+        self.counts.iter().enumerate().map(|(i, count)| {
+            let height = if density { *count as f64 / (total * self.width) } else { *count as f64 };
+            (self.start + (i as f64 + 0.5) * self.width, height)
+        }).collect()
+    }
+}
+
+/// The value below which a fraction `p` of the sorted values are
+fn quantile(sorted: &[f64], p: f64) -> f64 {
+    let position = p * (sorted.len() - 1) as f64;
+    let (below, above) = (sorted[position.floor() as usize], sorted[position.ceil() as usize]);
+    below + (above - below) * position.fract()
+}
+
+/// Splits the values, which must not be empty, into bins, the last one
+/// holding the largest value
+pub fn bin(values: &[f64], binning: Binning) -> Result<Bins, String> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let (mut low, mut high) = (sorted[0], sorted[sorted.len() - 1]);
+    // a single distinct value gets a bin around it
+    if low == high {
+        low -= 0.5;
+        high += 0.5;
+    }
+
+    let sturges = (sorted.len() as f64).log2().ceil() + 1.0;
+    let (start, count) = match binning {
+        Binning::Count(count) => (low, count as f64),
+        Binning::Width(width) => {
+            let start = (low / width).floor() * width;
+            (start, ((high - start) / width).floor() + 1.0)
+        },
+        Binning::Sturges => (low, sturges),
+        Binning::FreedmanDiaconis => {
+            let width = 2.0 * (quantile(&sorted, 0.75) - quantile(&sorted, 0.25)) / (sorted.len() as f64).cbrt();
+            match width > 0.0 {
+                true => (low, ((high - low) / width).ceil().max(1.0)),
+                false => (low, sturges),
+            }
+        },
+    };
+    if count > MAX_BINS {
+        return Err(format!("The values would be split into {} bins, more than {}", count, MAX_BINS));
+    }
+
+    let count = count as usize;
+    let width = match binning {
+        Binning::Width(width) => width,
+        _ => (high - start) / count as f64,
+    };
+    let mut counts = vec![0; count];
+    for value in sorted.iter() {
+        let index = ((value - start) / width).floor() as usize;
+        counts[index.min(count - 1)] += 1;
+    }
+
+    Ok(Bins { start, width, counts })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sturges_bins() {
+        let values: Vec<f64> = (1..=8).map(|i| i as f64).collect();
+        let bins = bin(&values, Binning::Sturges).unwrap();
+        assert_eq!(bins.start, 1.0);
+        assert_eq!(bins.width, 1.75);
+        assert_eq!(bins.counts, [2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn freedman_diaconis_bins() {
+        let values: Vec<f64> = (0..100).map(|i| i as f64).collect();
+        let bins = bin(&values, Binning::FreedmanDiaconis).unwrap();
+        assert_eq!(bins.counts, [20; 5]);
+
+        // without an interquartile range, Sturges is used
+        let bins = bin(&[1.0, 5.0, 5.0, 5.0, 5.0, 9.0], Binning::FreedmanDiaconis).unwrap();
+        assert_eq!(bins.counts, [1, 0, 4, 1]);
+    }
+
+    #[test]
+    fn width_bins_start_at_a_multiple_of_the_width() {
+        let bins = bin(&[1.0, 3.0, 4.0], Binning::Width(2.0)).unwrap();
+        assert_eq!(bins.start, 0.0);
+        assert_eq!(bins.counts, [1, 1, 1]);
+
+        assert!(bin(&[0.0, 1.0], Binning::Width(1e-4)).is_err());
+    }
+
+    #[test]
+    fn single_value_gets_a_bin_around_it() {
+        let bins = bin(&[3.0, 3.0], Binning::Count(2)).unwrap();
+        assert_eq!(bins.start, 2.5);
+        assert_eq!(bins.counts, [0, 2]);
+    }
+
+    #[test]
+    fn density_bars_have_an_area_of_one() {
+        let bins = bin(&[0.0, 1.0, 1.5, 2.0, 4.0], Binning::Width(0.5)).unwrap();
+        let area: f64 = bins.bars(true).iter().map(|(_, height)| height * bins.width).sum();
+        assert!((area - 1.0).abs() < 1e-12);
+    }
+}
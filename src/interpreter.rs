@@ -1,13 +1,20 @@
+use crate::diagnostic::{Code, Diagnostic};
 use crate::parser;
 use crate::parser::{Token, TokenType};
-use crate::parser::{
-    TOP_LEVEL_DECLARATIONS,
-    INNER_FUNCTIONS,
-};
+use crate::expander::{expression_error, Expander};
+use crate::transform::{Scale, Transform, PADDING, SUBTITLE_SIZE, TITLE_SIZE};
+use crate::axis;
+use crate::axis::NumberFormat;
+use crate::legend;
+use crate::legend::{Entry, Position, Swatch};
+use crate::data;
+use crate::data::Column;
+use crate::histogram;
+use crate::histogram::Binning;
 
 use exmex::prelude::*;
 
-use std::process::exit;
+use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
 pub struct Declaration {
@@ -15,6 +22,15 @@ pub struct Declaration {
     pub name: Option<String>,
     pub min: Option<f64>,
     pub max: f64,
+    /// step between the major ticks
+    pub step: Option<f64>,
+    /// number of minor ticks between two major ones
+    pub minor: Option<usize>,
+    pub fontsize: Option<f64>,
+    pub format: Option<NumberFormat>,
+    pub scale: Scale,
+    /// names written instead of the values 1, 2, 3, ...
+    pub categories: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone)]
@@ -23,6 +39,8 @@ pub struct Root {
     pub color: String,
     pub background: String,
     pub axis: (Declaration, Declaration),
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -30,7 +48,25 @@ pub struct Grid {
     pub color: String,
     pub alpha: Option<f64>,
     pub thickness: Option<f64>,
-    pub step: Option<f64>,
+    /// steps between the major lines, in the units of the axes, the major
+    /// ticks of the axes being followed when they are not given
+    pub xstep: Option<f64>,
+    pub ystep: Option<f64>,
+    /// number of minor lines between two major ones
+    pub minor: Option<usize>,
+    /// dash pattern of the lines, as an svg `stroke-dasharray`
+    pub dash: Option<String>,
+}
+
+/// Lists the named elements with a sample of how they are drawn
+#[derive(Debug, Clone)]
+pub struct Legend {
+    pub position: Position,
+    /// color of the names and of the border, the one of the root by default
+    pub color: Option<String>,
+    pub background: Option<String>,
+    pub alpha: Option<f64>,
+    pub fontsize: Option<f64>,
 }
 
 #[derive(Debug, Clone)]
@@ -39,15 +75,58 @@ pub struct Function {
     pub args: Vec<Arg>,
 }
 
+/// The expression of a graph, parsed once when the graph is processed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub source: String,
+    pub compiled: FlatEx<f64>,
+}
+
+impl Expression {
+    /// Evaluates the expression, which is either constant or a function of x
+    pub fn eval(&self, x: f64) -> Result<f64, Diagnostic> {
+        let values: &[f64] = if self.compiled.var_names().is_empty() { &[] } else { &[x] };
+        self.compiled.eval(values).map_err(|e| {
+            Diagnostic::error(Code::InvalidExpression, format!("Cannot evaluate function {} -> {}", self.source, expression_error(&e)))
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Arg {
     Name(String),
-    Func(String),
+    Func(Box<Expression>),
     Color(String),
     Thickness(f64),
     From((f64, f64)),
     To((f64, f64)),
     At((f64, f64)),
+    Samples(usize),
+    Resolution(f64),
+    Asymptotes(bool),
+    Clip(bool),
+    Content(String),
+    Size(f64),
+    Anchor(String),
+    Rotate(f64),
+    /// positioned in svg units instead of data coordinates
+    Pixel(bool),
+    Fill(String),
+    Alpha(f64),
+    /// radii along the x and y axes
+    Radius((f64, f64)),
+    Points(Vec<(f64, f64)>),
+    Marker(String),
+    /// dash pattern of the stroke, as an svg `stroke-dasharray`
+    Dash(String),
+    Curve(String),
+    Mode(String),
+    Width(f64),
+    Colors(Vec<String>),
+    /// index of a grouped bars function among the grouped ones, and their number
+    Slot((usize, usize)),
+    /// where each stacked bar starts
+    Bases(Vec<f64>),
 }
 
 pub struct Interpreter {
@@ -57,29 +136,32 @@ pub struct Interpreter {
     pub definitions: Vec<Declaration>,
     pub root: Option<Root>,
     pub grid: Option<Grid>,
+    pub legend: Option<Legend>,
     pub functions: Vec<Function>,
+    /// directory the data files are looked for in
+    pub base_dir: PathBuf,
+    /// axes defined without a 'max', fitted to the histograms: their name,
+    /// their definition and whether they set their 'min'
+    pub fitted: Vec<(String, Token, bool)>,
+    /// the '#grid' declaration and the keywords setting its x step, its y
+    /// step and its minor lines, checked once the axes are known
+    pub grid_keywords: Option<(Token, [Option<Token>; 3])>,
 }
 
 impl Interpreter {
     pub fn new(input: String) -> Self {
-        let mut lexer = parser::Lexer::new(input);
-        let tokens = lexer.tokenize();
-        match tokens {
-            Ok(t) => {
-                Interpreter {
-                    lexer,
-                    tokens: t,
-                    position: 0,
-                    definitions: Vec::new(),
-                    functions: Vec::new(),
-                    root: None,
-                    grid: None,
-                }
-            },
-            Err(e) => {
-                println!("[ERROR]: {}", e);
-                exit(1);
-            },
+        Interpreter {
+            lexer: parser::Lexer::new(input),
+            tokens: Vec::new(),
+            position: 0,
+            definitions: Vec::new(),
+            functions: Vec::new(),
+            root: None,
+            grid: None,
+            legend: None,
+            base_dir: PathBuf::new(),
+            fitted: Vec::new(),
+            grid_keywords: None,
         }
     }
 
@@ -103,59 +185,46 @@ impl Interpreter {
         Some(token)
     }
 
-    //// Functions for checking if each declaration is valid
-    //// has an end keyword
-    fn check_declarations(&mut self) {
+    //// Functions for checking if the root is present and there is only one
+    fn check_root(&mut self) -> Vec<Diagnostic> {
+        let mut errors: Vec<Diagnostic> = Vec::new();
+        let mut root_found: Option<Token> = None;
         let mut current_pos = 0;
         let mut current_token = self.get_token(current_pos);
         while current_token.is_some() {
             let token = current_token.unwrap();
-            if token.token_type == TokenType::KEYWORD {
-                if TOP_LEVEL_DECLARATIONS.contains(&token.value.as_str()) || INNER_FUNCTIONS.contains(&token.value.as_str()) {
-                    let mut end_found = false;
-                    let mut end_pos = current_pos + 1;
-                    let mut end_token = self.get_token(end_pos);
-                    while end_token.is_some() {
-                        let token = end_token.unwrap();
-                        if token.token_type == TokenType::KEYWORD && token.value == "end" {
-                            end_found = true;
-                            break;
-                        }
-                        end_pos += 1;
-                        end_token = self.get_token(end_pos);
-                    }
-                    if !end_found {
-                        println!("[ERROR]: Missing 'end' keyword for declaration '{}' at line {}", token.value, token.line);
-                        exit(1);
-                    }
+            if token.token_type == TokenType::DECLARATION && token.value == "root" {
+                if let Some(root) = &root_found {
+                    errors.push(
+                        Diagnostic::error(Code::Redefined, "Multiple 'root' declarations")
+                            .at(&token)
+                            .with_note(format!("The root is first declared at line {}", root.line))
+                    );
+                } else {
+                    root_found = Some(token);
                 }
             }
             current_pos += 1;
             current_token = self.get_token(current_pos);
         }
-        
+        if root_found.is_none() {
+            errors.push(Diagnostic::error(Code::MissingKeyword, "Missing 'root' declaration"));
+        }
+        errors
     }
 
-    //// Functions for checking if the root is present and there is only one
-    fn check_root(&mut self) {
-        let mut root_found = false;
-        let mut current_pos = 0;
-        let mut current_token = self.get_token(current_pos);
-        while current_token.is_some() {
-            let token = current_token.unwrap();
-            if token.token_type == TokenType::DECLARATION && token.value == "root" {
-                if root_found {
-                    println!("[ERROR]: Multiple 'root' declarations");
-                    exit(1);
-                }
-                root_found = true;
+    /// Skips what is left of a block that failed to be processed, stopping
+    /// on its `#end` or right before the next declaration or function
+    fn synchronize(&mut self) {
+        while let Some(token) = self.next() {
+            match token.token_type {
+                TokenType::DECLARATION if token.value == "end" => return,
+                TokenType::DECLARATION | TokenType::FUNCTION | TokenType::DEFINE => {
+                    self.position -= 1;
+                    return;
+                },
+                _ => self.consume(1),
             }
-            current_pos += 1;
-            current_token = self.get_token(current_pos);
-        }
-        if !root_found {
-            println!("[ERROR]: Missing 'root' declaration");
-            exit(1);
         }
     }
 
@@ -167,237 +236,718 @@ impl Interpreter {
         }
         None
     }
-    
-    /// Functions for processing the definition
-    fn process_define(&mut self) {
-        self.consume(1);
-        let mut current_token = self.next().clone();
+
+    /// Returns the next keyword of the body of `block`, or None once its
+    /// `#end` is reached
+    fn next_keyword(&mut self, block: &Token) -> Result<Option<Token>, Diagnostic> {
+        let current_token = self.next();
         if current_token.is_none() {
-            println!("[ERROR]: Missing variable name after 'define' keyword at line {}", current_token.unwrap().line);
-            exit(1);
+            return Err(
+                Diagnostic::error(Code::MissingEnd, format!("Missing '#end' for '{}'", block.value))
+                    .at(block)
+                    .with_help("Add '#end' at the end of the file")
+            );
         }
 
         let token = current_token.unwrap();
-        if token.token_type != TokenType::VARNAME {
-            println!("[ERROR]: Missing variable name after 'define' keyword at line {}", token.line);
-            exit(1);
+        if token.token_type == TokenType::DECLARATION && token.value == "end" {
+            return Ok(None);
         }
-        
+
+        // a new block starting means the current one was never closed
+        if matches!(token.token_type, TokenType::DECLARATION | TokenType::FUNCTION | TokenType::DEFINE) {
+            let prefix = if token.token_type == TokenType::FUNCTION { "@" } else { "#" };
+            return Err(
+                Diagnostic::error(Code::MissingEnd, format!("Missing '#end' for '{}'", block.value))
+                    .at(block)
+                    .with_note(format!("'{}{}' at line {} starts before '{}' is closed", prefix, token.value, token.line, block.value))
+                    .with_help(format!("Add '#end' before the '{}{}' at line {}", prefix, token.value, token.line))
+            );
+        }
+
+        if token.token_type != TokenType::KEYWORD {
+            return Err(
+                Diagnostic::error(Code::UnexpectedToken, format!("Unexpected token '{}'", token.value))
+                    .at(&token)
+                    .with_note("Expected a keyword")
+            );
+        }
+
+        Ok(Some(token))
+    }
+
+    fn unexpected_keyword(&self, token: &Token, block: &Token, allowed: &[&str]) -> Diagnostic {
+        Diagnostic::error(Code::UnexpectedToken, format!("Unexpected keyword '{}' in '{}'", token.value, block.value))
+            .at(token)
+            .with_note(format!("Expected one of the following: {}", allowed.join(", ")))
+    }
+
+    /// Reads the value following `keyword`, which must be one of `allow_tokens`
+    fn get_value(&mut self, keyword: &Token, allow_tokens: Vec<TokenType>, expected: &str) -> Result<Token, Diagnostic> {
         self.consume(1);
-        
-        let varname = token.value;
-        let mut min = None;
-        let mut max = None;
-        let mut name = None;
+        let current_token = self.next();
+        if current_token.is_none() {
+            return Err(
+                Diagnostic::error(Code::MissingValue, format!("Missing value after '{}' keyword", keyword.value))
+                    .at(keyword)
+            );
+        }
+
+        let token = current_token.unwrap();
+        if !allow_tokens.contains(&token.token_type) {
+            return Err(
+                Diagnostic::error(Code::UnexpectedToken, format!("Unexpected token '{}'", token.value))
+                    .at(&token)
+                    .with_note(format!("Expected {}", expected))
+            );
+        }
 
-        current_token = self.next();
+        Ok(token)
+    }
 
-        while current_token.is_some() {
-            let token = current_token.unwrap();
-            if token.token_type == TokenType::DECLARATION && token.value == "end" {
+    fn get_string(&mut self, keyword: &Token) -> Result<String, Diagnostic> {
+        let token = self.get_value(keyword, vec![TokenType::STRING], "a string")?;
+        Ok(token.value)
+    }
+
+    fn get_hex(&mut self, keyword: &Token) -> Result<String, Diagnostic> {
+        let token = self.get_value(keyword, vec![TokenType::HEX], "a hexadecimal value")?;
+        parse_color(token)
+    }
+
+    /// Values separated by commas, the list going on after a comma
+    fn get_list(&mut self, keyword: &Token, allow_tokens: Vec<TokenType>, expected: &str) -> Result<Vec<Token>, Diagnostic> {
+        let mut values = vec![self.get_value(keyword, allow_tokens.clone(), expected)?];
+        while let (Some(comma), Some(token)) = (self.get_token(self.position + 1), self.get_token(self.position + 2)) {
+            if comma.token_type != TokenType::SYMBOL || comma.value != "," {
                 break;
             }
-
-            if token.token_type != TokenType::KEYWORD {
-                println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                println!("         > Expected a keyword");
-                exit(1);
+            if !allow_tokens.contains(&token.token_type) {
+                return Err(
+                    Diagnostic::error(Code::UnexpectedToken, format!("Unexpected token '{}'", token.value))
+                        .at(&token)
+                        .with_note(format!("Expected {}", expected))
+                );
             }
+            values.push(token);
+            self.consume(2);
+        }
+        Ok(values)
+    }
+
+    /// A dash pattern, lengths of dashes and gaps separated by spaces
+    fn get_dash(&mut self, keyword: &Token) -> Result<String, Diagnostic> {
+        let token = self.get_value(keyword, vec![TokenType::STRING], "a string")?;
+        let lengths = token.value.split_whitespace().map(|length| length.parse::<f64>()).collect::<Result<Vec<f64>, _>>();
+        match lengths {
+            Ok(lengths) if !lengths.is_empty() && lengths.iter().all(|length| *length >= 0.0) && lengths.iter().any(|length| *length > 0.0) => {
+                Ok(lengths.iter().map(|length| length.to_string()).collect::<Vec<_>>().join(" "))
+            },
+            _ => Err(
+                Diagnostic::error(Code::InvalidValue, format!("Invalid dash pattern '{}'", token.value))
+                    .at(&token)
+                    .with_note("A dash pattern is a list of positive lengths, alternately dashes and gaps")
+                    .with_help("Write dash patterns like \"4 2\"")
+            ),
+        }
+    }
+
+    fn get_bool(&mut self, keyword: &Token) -> Result<bool, Diagnostic> {
+        let token = self.get_value(keyword, vec![TokenType::BOOLEAN], "'true' or 'false'")?;
+        Ok(token.value == "true")
+    }
+
+    fn get_number(&mut self, keyword: &Token) -> Result<f64, Diagnostic> {
+        let token = self.get_value(
+            keyword,
+            vec![TokenType::FLOAT, TokenType::INTERGER],
+            "a float or an integer"
+        )?;
+        parse_number(&token)
+    }
+
+    /// Reads and parses the expression of a graph, whose only variable is x
+    fn get_expression(&mut self, keyword: &Token) -> Result<Expression, Diagnostic> {
+        let token = self.get_value(keyword, vec![TokenType::STRING], "a string")?;
+        let compiled = exmex::parse::<f64>(&token.value).map_err(|e| {
+            Diagnostic::error(Code::InvalidExpression, format!("Cannot parse function '{}'", token.value))
+                .at(&token)
+                .with_note(expression_error(&e))
+        })?;
+
+        if let Some(name) = compiled.var_names().iter().find(|name| name.as_str() != "x") {
+            return Err(
+                Diagnostic::error(Code::Undefined, format!("Unknown variable '{}' in function '{}'", name, token.value))
+                    .at(&token)
+                    .with_help("A function can only use the variable 'x'")
+            );
+        }
 
-            if token.token_type == TokenType::KEYWORD && token.value == "min" {
+        Ok(Expression {
+            source: token.value,
+            compiled,
+        })
+    }
+
+    fn get_tuple(&mut self, len: usize, allow_tokens: Vec<TokenType>, keyword: &Token) -> Result<Vec<Token>, Diagnostic> {
+        let token_strings: Vec<String> = allow_tokens.iter().map(|x| self.lexer.get_human_readable(x.clone())).collect();
+        let mut tuple: Vec<Token> = Vec::new();
+        self.consume(1);
+        while tuple.len() < len {
+            let mut current_token = self.next();
+            let is_comma = current_token.as_ref().is_some_and(|t| t.token_type == TokenType::SYMBOL && t.value == ",");
+            if is_comma && !tuple.is_empty() {
                 self.consume(1);
                 current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'min' keyword at line {}", token.line);
-                    exit(1);
-                }
-                let token = current_token.unwrap();
-                if token.token_type != TokenType::INTERGER && token.token_type != TokenType::FLOAT {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected an integer or a float");
-                    exit(1);
-                }
-                
-                min = Some(token.value.parse::<f64>().unwrap());
             }
 
-            if token.token_type == TokenType::KEYWORD && token.value == "max" {
-                self.consume(1);
-                current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'max' keyword at line {}", token.line);
-                    exit(1);
-                }
-                let token = current_token.unwrap();
-                if token.token_type != TokenType::INTERGER && token.token_type != TokenType::FLOAT {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected an integer or a float");
-                    exit(1);
-                }
-                
-                max = Some(token.value.parse::<f64>().unwrap());
+            match current_token {
+                Some(token) if allow_tokens.contains(&token.token_type) => {
+                    tuple.push(token);
+                    self.consume(1);
+                },
+                Some(token) if tuple.is_empty() || is_comma => {
+                    return Err(
+                        Diagnostic::error(Code::UnexpectedToken, format!("Unexpected token '{}'", token.value))
+                            .at(&token)
+                            .with_note(format!("Expected one of the following: {:?}", token_strings))
+                    );
+                },
+                _ => {
+                    return Err(
+                        Diagnostic::error(Code::TupleArity, format!("Missing values for '{}' keyword", keyword.value))
+                            .at(keyword)
+                            .with_note(format!("'{}' takes {} values, found {}", keyword.value, len, tuple.len()))
+                            .with_help(self.tuple_help(keyword, len, &allow_tokens))
+                    );
+                },
             }
+        }
 
-            if token.token_type == TokenType::KEYWORD && token.value == "name" {
-                self.consume(1);
-                current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'name' keyword at line {}", token.line);
-                    exit(1);
-                }
-                let token = current_token.unwrap();
-                if token.token_type != TokenType::STRING {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a string");
-                    exit(1);
-                }
-                
-                name = Some(token.value);
+        // `box 0, 0, 100, 100, 50` has one value too many
+        let mut extra: Vec<Token> = Vec::new();
+        while let (Some(comma), Some(token)) = (self.next(), self.get_token(self.position + 1)) {
+            if comma.token_type != TokenType::SYMBOL || comma.value != "," || !allow_tokens.contains(&token.token_type) {
+                break;
+            }
+            extra.push(token);
+            self.consume(2);
+        }
+        if let Some(first) = extra.first() {
+            return Err(
+                Diagnostic::error(Code::TupleArity, format!("Too many values for '{}' keyword", keyword.value))
+                    .at(first)
+                    .with_note(format!("'{}' takes {} values, found {}", keyword.value, len, len + extra.len()))
+                    .with_help(self.tuple_help(keyword, len, &allow_tokens))
+            );
+        }
+
+        self.position -= 1;
+
+        Ok(tuple)
+    }
+
+    fn tuple_help(&self, keyword: &Token, len: usize, allow_tokens: &[TokenType]) -> String {
+        let example: Vec<String> = if allow_tokens.contains(&TokenType::VAR) {
+            let names: Vec<String> = self.definitions.iter().map(|d| d.varname.clone()).take(len).collect();
+            if names.len() == len { names } else { vec!["x".to_string(), "y".to_string()] }
+        } else {
+            vec!["0".to_string(); len]
+        };
+        format!(
+            "'{}' takes {} values separated by commas, like '{} {}'",
+            keyword.value, len, keyword.value, example.join(", ")
+        )
+    }
+
+    fn get_point(&mut self, keyword: &Token) -> Result<(f64, f64), Diagnostic> {
+        let values = self.get_tuple(
+            2,
+            vec![TokenType::FLOAT, TokenType::INTERGER],
+            keyword
+        )?;
+
+        Ok((parse_number(&values[0])?, parse_number(&values[1])?))
+    }
+
+    /// A list of points written `(x, y), (x, y), ...`
+    fn get_points(&mut self, keyword: &Token) -> Result<Vec<(f64, f64)>, Diagnostic> {
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        self.consume(1);
+        loop {
+            match self.next() {
+                Some(token) if token.token_type == TokenType::SYMBOL && token.value == "(" => {},
+                Some(token) => {
+                    return Err(
+                        Diagnostic::error(Code::UnexpectedToken, format!("Unexpected token '{}'", token.value))
+                            .at(&token)
+                            .with_note("Expected '(' starting a point")
+                            .with_help(format!("Write the points like '{} (0, 0), (1, 0), (0, 1)'", keyword.value))
+                    );
+                },
+                None => {
+                    return Err(
+                        Diagnostic::error(Code::MissingValue, format!("Missing value after '{}' keyword", keyword.value))
+                            .at(keyword)
+                    );
+                },
             }
 
+            points.push(self.get_point(keyword)?);
             self.consume(1);
-            current_token = self.next();
+            match self.next() {
+                Some(token) if token.token_type == TokenType::SYMBOL && token.value == ")" => {},
+                token => {
+                    let token = token.unwrap_or(keyword.clone());
+                    return Err(
+                        Diagnostic::error(Code::UnexpectedToken, "Missing ')' closing a point")
+                            .at(&token)
+                            .with_help(format!("Write the points like '{} (0, 0), (1, 0), (0, 1)'", keyword.value))
+                    );
+                },
+            }
+
+            // the list goes on after a comma
+            match self.get_token(self.position + 1) {
+                Some(token) if token.token_type == TokenType::SYMBOL && token.value == "," => self.consume(2),
+                _ => break,
+            }
+        }
+
+        Ok(points)
+    }
+
+    /// The x and y columns of a data file, by name or by position from 1
+    fn get_columns(&mut self, keyword: &Token) -> Result<(Column, Column), Diagnostic> {
+        let values = self.get_tuple(2, vec![TokenType::STRING, TokenType::INTERGER], keyword)?;
+        let column = |token: &Token| match token.token_type {
+            TokenType::STRING => Ok(Column::Name(token.value.clone())),
+            _ => match token.value.parse::<usize>() {
+                Ok(index) if index > 0 => Ok(Column::Index(index)),
+                _ => Err(
+                    Diagnostic::error(Code::InvalidValue, format!("Invalid column {}", token.value))
+                        .at(token)
+                        .with_note("The first column is 1")
+                ),
+            },
+        };
+        Ok((column(&values[0])?, column(&values[1])?))
+    }
+
+    /// Reads the `columns` of the csv file named by `file`, which is
+    /// relative to the directory of the source, `option` being a 'columns'
+    /// or 'header' keyword that needs a file
+    fn load_data(&self, file: Option<&Token>, columns: &[Column], header: Option<bool>, option: Option<&Token>) -> Result<Vec<Vec<f64>>, Diagnostic> {
+        match (file, option) {
+            (Some(file), _) => {
+                data::read(&self.base_dir.join(&file.value), columns, header.unwrap_or(true)).map_err(|e| e.at(file))
+            },
+            (None, Some(token)) => Err(
+                Diagnostic::error(Code::MissingKeyword, format!("Missing 'data' keyword for '{}'", token.value))
+                    .at(token)
+                    .with_note(format!("'{}' selects what is read from a data file", token.value))
+            ),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+
+    /// The points of a data file, from its first two columns by default
+    fn load_points(&self, file: Option<&Token>, columns: Option<(Column, Column)>, header: Option<bool>, option: Option<&Token>) -> Result<Vec<(f64, f64)>, Diagnostic> {
+        let (x, y) = columns.unwrap_or((Column::Index(1), Column::Index(2)));
+        let rows = self.load_data(file, &[x, y], header, option)?;
+        Ok(rows.iter().map(|row| (row[0], row[1])).collect())
+    }
+
+    /// A radius, or the radii along the x and y axes separated by a comma
+    fn get_radius(&mut self, keyword: &Token) -> Result<(f64, f64), Diagnostic> {
+        let token = self.get_value(keyword, vec![TokenType::FLOAT, TokenType::INTERGER], "a float or an integer")?;
+        let value = parse_number(&token)?;
+        let mut radius = (value, value);
+        let is_pair = self.get_token(self.position + 1).is_some_and(|t| t.token_type == TokenType::SYMBOL && t.value == ",")
+            && self.get_token(self.position + 2).is_some_and(|t| [TokenType::FLOAT, TokenType::INTERGER].contains(&t.token_type));
+        if is_pair {
+            self.consume(2);
+            radius.1 = parse_number(&self.next().unwrap())?;
+        }
+
+        if radius.0 <= 0.0 || radius.1 <= 0.0 {
+            return Err(
+                Diagnostic::error(Code::InvalidValue, "Radius value must be greater than 0")
+                    .at(keyword)
+            );
+        }
+        Ok(radius)
+    }
+
+    /// Functions for processing the definition
+    fn process_define(&mut self) -> Result<(), Diagnostic> {
+        let define = self.next().unwrap();
+        self.consume(1);
+        let current_token = self.next();
+        if current_token.is_none() || current_token.as_ref().unwrap().token_type != TokenType::VARNAME {
+            return Err(
+                Diagnostic::error(Code::MissingValue, "Missing variable name after 'define' keyword")
+                    .at(&define)
+            );
+        }
+
+        let varname = current_token.unwrap().value;
+        self.consume(1);
+
+        let mut min = None;
+        let mut max = None;
+        let mut name = None;
+        let mut step = None;
+        let mut minor = None;
+        let mut fontsize = None;
+        let mut format = None;
+        let mut scale = None;
+        let mut base = None;
+        let mut threshold = None;
+        let mut categories: Option<Vec<String>> = None;
+        // a keyword about numbers, which doesn't apply to categories
+        let mut numeric: Option<Token> = None;
+
+        while let Some(token) = self.next_keyword(&define)? {
+            if !["name", "fontsize", "categories"].contains(&token.value.as_str()) {
+                numeric = Some(token.clone());
+            }
+            match token.value.as_str() {
+                "categories" => {
+                    let values = self.get_list(&token, vec![TokenType::STRING], "a string")?;
+                    categories = Some(values.into_iter().map(|value| value.value).collect());
+                },
+                "min" => min = Some(self.get_number(&token)?),
+                "max" => max = Some(self.get_number(&token)?),
+                "name" => name = Some(self.get_string(&token)?),
+                "step" => {
+                    let step_value = self.get_number(&token)?;
+                    if step_value <= 0.0 {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Step value must be greater than 0")
+                                .at(&token)
+                        );
+                    }
+                    step = Some((step_value, token));
+                },
+                "minor" => {
+                    let minor_value = self.get_number(&token)?;
+                    if minor_value < 0.0 || minor_value.fract() != 0.0 {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "The number of minor ticks must be a positive integer")
+                                .at(&token)
+                        );
+                    }
+                    minor = Some(minor_value as usize);
+                },
+                "fontsize" => {
+                    let fontsize_value = self.get_number(&token)?;
+                    if fontsize_value <= 0.0 {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Font size must be greater than 0")
+                                .at(&token)
+                        );
+                    }
+                    fontsize = Some(fontsize_value);
+                },
+                "format" => {
+                    let pattern = self.get_value(&token, vec![TokenType::STRING], "a string")?;
+                    let number_format = NumberFormat::parse(&pattern.value).map_err(|e| {
+                        Diagnostic::error(Code::InvalidValue, format!("Invalid number format '{}'", pattern.value))
+                            .at(&pattern)
+                            .with_note(e)
+                            .with_help("Write the number as '{}', '{:.2}' for 2 decimals or '{:e}' for a scientific notation")
+                    })?;
+                    format = Some(number_format);
+                },
+                "scale" => {
+                    let scale_name = self.get_value(&token, vec![TokenType::STRING], "a string")?;
+                    if !["linear", "log", "symlog"].contains(&scale_name.value.as_str()) {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, format!("Unknown scale '{}'", scale_name.value))
+                                .at(&scale_name)
+                                .with_help("The scale is one of 'linear', 'log' or 'symlog'")
+                        );
+                    }
+                    scale = Some(scale_name);
+                },
+                "base" => {
+                    let base_value = self.get_number(&token)?;
+                    if base_value <= 1.0 {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Base value must be greater than 1")
+                                .at(&token)
+                        );
+                    }
+                    base = Some((base_value, token));
+                },
+                "threshold" => {
+                    let threshold_value = self.get_number(&token)?;
+                    if threshold_value <= 0.0 {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Threshold value must be greater than 0")
+                                .at(&token)
+                        );
+                    }
+                    threshold = Some((threshold_value, token));
+                },
+                _ => {
+                    return Err(self.unexpected_keyword(&token, &define, &["min", "max", "name", "step", "minor", "fontsize", "format", "scale", "base", "threshold", "categories"]));
+                },
+            }
+
+            self.consume(1);
+        }
+
+        // the categories are at 1, 2, 3, ... with half a step around them
+        if let Some(categories) = &categories {
+            if let Some(token) = numeric {
+                return Err(
+                    Diagnostic::error(Code::InvalidValue, format!("'{}' doesn't apply to an axis with categories", token.value))
+                        .at(&token)
+                        .with_note(format!("The range of '{}' is set by its {} categories", varname, categories.len()))
+                );
+            }
+            min = Some(0.5);
+            max = Some(categories.len() as f64 + 0.5);
         }
 
+        let has_min = min.is_some();
         if min.is_none() {
             min = Some(0.0);
         }
-        if max.is_none() {
-            println!("[ERROR]: Missing 'max' keyword");
-            println!("         > Need to specify a maximum value for the variable '{}'", varname);
-            exit(1);
+
+        let scale_name = scale.as_ref().map_or("linear", |token| token.value.as_str());
+        if let Some((_, token)) = base.as_ref().filter(|_| scale_name == "linear") {
+            return Err(
+                Diagnostic::error(Code::InvalidValue, "'base' only applies to logarithmic scales")
+                    .at(token)
+                    .with_help("Add 'scale \"log\"' or 'scale \"symlog\"' to the definition")
+            );
+        }
+        if let Some((_, token)) = threshold.as_ref().filter(|_| scale_name != "symlog") {
+            return Err(
+                Diagnostic::error(Code::InvalidValue, "'threshold' only applies to the 'symlog' scale")
+                    .at(token)
+                    .with_help("Add 'scale \"symlog\"' to the definition")
+            );
+        }
+        let base = base.map_or(10.0, |(base, _)| base);
+        let scale = match scale_name {
+            "log" => Scale::Log { base },
+            "symlog" => Scale::Symlog { base, threshold: threshold.map_or(1.0, |(threshold, _)| threshold) },
+            _ => Scale::Linear,
+        };
+
+        if matches!(scale, Scale::Log { .. }) && min.unwrap() <= 0.0 {
+            return Err(
+                Diagnostic::error(Code::InvalidValue, format!("Invalid range for the logarithmic variable '{}'", varname))
+                    .at(&define)
+                    .with_note(format!("'min' is {}, but a logarithmic scale only has positive values", min.unwrap()))
+                    .with_help("Set 'min' to a positive value, like 'min 1', or use 'scale \"symlog\"'")
+            );
         }
 
         let declaration = Declaration {
             varname,
             name,
             min,
-            max: max.unwrap(),
+            // set once the histograms are known
+            max: max.unwrap_or(f64::NAN),
+            step: step.as_ref().map(|(step, _)| *step),
+            minor,
+            fontsize,
+            format,
+            scale,
+            categories,
         };
 
+        if max.is_none() {
+            self.fitted.push((declaration.varname.clone(), define, has_min));
+        } else {
+            check_range(&declaration, &define, step.as_ref().map(|(_, token)| token))?;
+        }
+
         self.definitions.push(declaration);
-        
+        Ok(())
     }
 
-    fn process_declaration(&mut self) {
-        let declaration_name = self.next().clone().unwrap().value;
-        self.consume(1);
-        let mut current_token = self.next();
-        if current_token.is_none() {
-            println!("[ERROR]: Missing body for declaration '{}' at line {}", declaration_name, current_token.unwrap().line);
-            exit(1);
-        }
-
-        if declaration_name == "root".to_string() {
-            let mut _box: Option<(f64, f64, f64, f64)> = None;
-            let mut color: Option<String> = None;
-            let mut background: Option<String> = None;
-            let mut axis: Option<(Declaration, Declaration)> = None;
-            while current_token.is_some() {
-                let token = current_token.unwrap();
-                if token.token_type == TokenType::DECLARATION && token.value == "end" {
-                    break;
+    /// Sets the range of the axes defined without a 'max' to the one of the
+    /// histograms, rounded to a step of the axis
+    fn fit_axes(&mut self) -> Vec<Diagnostic> {
+        let mut errors: Vec<Diagnostic> = Vec::new();
+        // the edges and the height of every bin
+        let bins: Vec<(f64, f64, f64)> = self.functions.iter().filter(|func| func.name == "histogram").flat_map(|func| {
+            let datas = collect_args(func);
+            let width = datas.width.unwrap();
+            datas.points.unwrap().into_iter().map(move |(x, height)| (x - width / 2.0, x + width / 2.0, height))
+        }).collect();
+        // a histogram with an error is already reported
+        let has_histogram = self.tokens.iter().any(|token| token.token_type == TokenType::FUNCTION && token.value == "histogram");
+
+        for (varname, define, has_min) in std::mem::take(&mut self.fitted) {
+            let missing = Diagnostic::error(Code::MissingKeyword, "Missing 'max' keyword")
+                .at(&define)
+                .with_note(format!("Need to specify a maximum value for the variable '{}'", varname))
+                .with_help("The range of an axis without a 'max' is fitted to the histograms, when there are some");
+            // without a valid root, the axes are unknown
+            let Some(root) = self.root.as_mut() else {
+                if !has_histogram {
+                    errors.push(missing);
                 }
+                continue;
+            };
 
-                if token.token_type != TokenType::KEYWORD {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a keyword");
-                    exit(1);
+            let (axis, low, high) = if bins.is_empty() {
+                if !has_histogram {
+                    errors.push(missing);
                 }
+                continue;
+            } else if root.axis.0.varname == varname {
+                let low = bins.iter().map(|bin| bin.0).fold(f64::INFINITY, f64::min);
+                (&mut root.axis.0, low, bins.iter().map(|bin| bin.1).fold(f64::NEG_INFINITY, f64::max))
+            } else if root.axis.1.varname == varname {
+                (&mut root.axis.1, 0.0, bins.iter().map(|bin| bin.2).fold(f64::NEG_INFINITY, f64::max))
+            } else {
+                errors.push(missing);
+                continue;
+            };
 
-                if token.token_type == TokenType::KEYWORD && token.value == "box" {
-                    self.consume(1);
-                    let values = self.get_tuple(
-                                 4, 
-                        vec![TokenType::FLOAT, TokenType::INTERGER],
-                        "box".to_string()
-                    );
+            let min = if has_min { axis.min.unwrap() } else { low };
+            let step = axis::nice_step(min, high);
+            if high > min {
+                axis.min = Some(if has_min { min } else { (min / step).floor() * step });
+                axis.max = (high / step).ceil() * step;
+            } else {
+                axis.max = high;
+            }
 
-                    if values.len() != 4 {
-                        println!("[ERROR]: Missing values for 'box' keyword at line {}", token.line);
-                        println!("         > Need to specify 4 values");
-                        exit(1);
+            match check_range(axis, &define, None) {
+                Ok(()) => {
+                    let axis = axis.clone();
+                    if let Some(definition) = self.definitions.iter_mut().find(|definition| definition.varname == varname) {
+                        *definition = axis;
                     }
+                },
+                Err(e) => errors.push(e),
+            }
+        }
 
-                    _box = Some((
-                        values[0].value.parse::<f64>().unwrap(),
-                        values[1].value.parse::<f64>().unwrap(),
-                        values[2].value.parse::<f64>().unwrap(),
-                        values[3].value.parse::<f64>().unwrap(),
-                    ));
-                }
-
-                if token.token_type == TokenType::KEYWORD && token.value == "color" {
-                    self.consume(1);
-                    current_token = self.next();
-                    if current_token.is_none() {
-                        println!("[ERROR]: Missing value after 'color' keyword at line {}", token.line);
-                        exit(1);
-                    }
-                    let token = current_token.unwrap();
-                    if token.token_type != TokenType::HEX {
-                        println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                        println!("         > Expected a hexadecimal value");
-                        exit(1);
-                    }
-                    
-                    color = Some(token.value);
-                }
+        errors
+    }
 
-                if token.token_type == TokenType::KEYWORD && token.value == "background" {
-                    self.consume(1);
-                    current_token = self.next();
-                    if current_token.is_none() {
-                        println!("[ERROR]: Missing value after 'background' keyword at line {}", token.line);
-                        exit(1);
-                    }
-                    let token = current_token.unwrap();
-                    if token.token_type != TokenType::HEX {
-                        println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                        println!("         > Expected a hexadecimal value");
-                        exit(1);
-                    }
-                    
-                    background = Some(token.value);
-                }
+    /// Checks that the steps of the grid don't draw too many lines along
+    /// the axes of the root, which may be declared after the grid
+    fn check_grid(&self) -> Option<Diagnostic> {
+        let (grid, root) = (self.grid.as_ref()?, self.root.as_ref()?);
+        let (declaration, [xstep, ystep, minor]) = self.grid_keywords.as_ref()?;
+        let axes = [(&root.axis.0, grid.xstep, xstep, "xstep"), (&root.axis.1, grid.ystep, ystep, "ystep")];
+        for (axis, step, step_keyword, keyword) in axes {
+            let axis = grid_axis(grid, axis, step);
+            if axis::tick_count(&axis) > axis::MAX_TICKS {
+                // the minor lines are at fault if the major ones are few enough
+                let culprit = match axis::tick_count(&Declaration { minor: None, ..axis.clone() }) > axis::MAX_TICKS {
+                    true => step_keyword,
+                    false => minor,
+                };
+                return Some(
+                    Diagnostic::error(Code::InvalidValue, format!("Too many grid lines along the variable '{}'", axis.varname))
+                        .at(culprit.as_ref().unwrap_or(declaration))
+                        .with_note(format!("A grid can't have more than {} lines along an axis", axis::MAX_TICKS))
+                        .with_help(format!("Use a larger '{}' or fewer 'minor' lines in '#grid'", keyword))
+                );
+            }
+        }
+        None
+    }
 
-                if token.token_type == TokenType::KEYWORD && token.value == "axis" {
-                    self.consume(1);
-                    let values = self.get_tuple(
-                                2, 
-                        vec![TokenType::VAR],
-                        "axis".to_string()
-                    );
+    fn process_declaration(&mut self) -> Result<(), Diagnostic> {
+        let declaration = self.next().unwrap();
+        self.consume(1);
 
-                    axis = Some(
-                        (
-                            self.get_var(values[0].clone().value).unwrap(),
-                            self.get_var(values[1].clone().value).unwrap(),
-                        )
-                    );
+        if declaration.value == "root" {
+            let mut _box: Option<(f64, f64, f64, f64)> = None;
+            let mut color: Option<String> = None;
+            let mut background: Option<String> = None;
+            let mut axis: Option<(Declaration, Declaration)> = None;
+            let mut title: Option<String> = None;
+            let mut subtitle: Option<String> = None;
+
+            while let Some(token) = self.next_keyword(&declaration)? {
+                match token.value.as_str() {
+                    "box" => {
+                        let values = self.get_tuple(
+                            4,
+                            vec![TokenType::FLOAT, TokenType::INTERGER],
+                            &token
+                        )?;
+
+                        _box = Some((
+                            parse_number(&values[0])?,
+                            parse_number(&values[1])?,
+                            parse_number(&values[2])?,
+                            parse_number(&values[3])?,
+                        ));
+                        if let Some((_, _, w, h)) = _box {
+                            if w <= 0.0 || h <= 0.0 {
+                                return Err(
+                                    Diagnostic::error(Code::InvalidValue, "The width and height of the box must be greater than 0")
+                                        .at(&token)
+                                );
+                            }
+                        }
+                    },
+                    "color" => color = Some(self.get_hex(&token)?),
+                    "background" => background = Some(self.get_hex(&token)?),
+                    "axis" => {
+                        let values = self.get_tuple(
+                            2,
+                            vec![TokenType::VAR],
+                            &token
+                        )?;
+
+                        let mut axes = Vec::new();
+                        for value in values {
+                            let var = self.get_var(value.value.clone()).ok_or(
+                                Diagnostic::error(Code::Undefined, format!("Undefined axis '{}'", value.value))
+                                    .at(&value)
+                                    .with_note("An axis must be defined with '#define' before the root")
+                            )?;
+                            axes.push(var);
+                        }
+                        axis = Some((axes[0].clone(), axes[1].clone()));
+                    },
+                    "title" => title = Some(self.get_string(&token)?),
+                    "subtitle" => subtitle = Some(self.get_string(&token)?),
+                    _ => {
+                        return Err(self.unexpected_keyword(&token, &declaration, &["box", "color", "background", "axis", "title", "subtitle"]));
+                    },
                 }
-    
+
                 self.consume(1);
-                current_token = self.next();
             }
 
             if _box.is_none() {
-                println!("[ERROR]: Missing 'box' keyword");
-                println!("         > Need to specify a box for the root");
-                exit(1);
+                return Err(
+                    Diagnostic::error(Code::MissingKeyword, "Missing 'box' keyword")
+                        .at(&declaration)
+                        .with_note("Need to specify a box for the root")
+                );
             }
             if color.is_none() {
                 color = Some("000000".to_string());
             }
-            
+
             if background.is_none() {
                 background = Some("ffffff".to_string());
             }
 
             if axis.is_none() {
-                println!("[ERROR]: Missing 'axis' keyword");
-                println!("         > Need to specify axis for the root");
-                exit(1);
+                return Err(
+                    Diagnostic::error(Code::MissingKeyword, "Missing 'axis' keyword")
+                        .at(&declaration)
+                        .with_note("Need to specify axis for the root")
+                );
             }
 
             let root = Root {
@@ -405,108 +955,71 @@ impl Interpreter {
                 color: color.unwrap(),
                 background: background.unwrap(),
                 axis: axis.unwrap(),
+                title,
+                subtitle,
             };
 
             self.root = Some(root);
 
-        } else if declaration_name == "grid".to_string() {
+        } else if declaration.value == "grid" {
             let mut color: Option<String> = None;
             let mut alpha: Option<f64> = None;
             let mut thickness: Option<f64> = None;
-            let mut step: Option<f64> = None;
-
-            while current_token.is_some() {
-                let token = current_token.unwrap();
-                if token.token_type == TokenType::DECLARATION && token.value == "end" {
-                    break;
-                }
-
-                if token.token_type != TokenType::KEYWORD {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a keyword");
-                    exit(1);
-                }
-
-                if token.token_type == TokenType::KEYWORD && token.value == "color" {
-                    self.consume(1);
-                    current_token = self.next();
-                    if current_token.is_none() {
-                        println!("[ERROR]: Missing value after 'color' keyword at line {}", token.line);
-                        exit(1);
-                    }
-                    let token = current_token.unwrap();
-                    if token.token_type != TokenType::HEX {
-                        println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                        println!("         > Expected a hexadecimal value");
-                        exit(1);
-                    }
-                    
-                    color = Some(token.value);
-                }
-
-                if token.token_type == TokenType::KEYWORD && token.value == "alpha" {
-                    self.consume(1);
-                    current_token = self.next();
-                    if current_token.is_none() {
-                        println!("[ERROR]: Missing value after 'alpha' keyword at line {}", token.line);
-                        exit(1);
-                    }
-                    let token = current_token.unwrap();
-                    if token.token_type == TokenType::FLOAT || token.token_type == TokenType::INTERGER {
-                        let alpha_value = token.value.parse::<f64>().unwrap();
-                        if alpha_value > 1.0 {
-                            println!("[ERROR]: Alpha value must be between 0 and 1 at line {}", token.line);
-                            exit(1);
+            let mut xstep: Option<f64> = None;
+            let mut ystep: Option<f64> = None;
+            let mut minor: Option<usize> = None;
+            let mut dash: Option<String> = None;
+            let mut keywords: [Option<Token>; 3] = [None, None, None];
+
+            while let Some(token) = self.next_keyword(&declaration)? {
+                match token.value.as_str() {
+                    "color" => color = Some(self.get_hex(&token)?),
+                    "alpha" => {
+                        let alpha_value = self.get_number(&token)?;
+                        if !(0.0..=1.0).contains(&alpha_value) {
+                            return Err(
+                                Diagnostic::error(Code::InvalidValue, "Alpha value must be between 0 and 1")
+                                    .at(&token)
+                            );
                         }
                         alpha = Some(alpha_value);
-                    } else {
-                        println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                        println!("         > Expected a float or an integer");
-                        exit(1);
-                    }
-                }
-
-                if token.token_type == TokenType::KEYWORD && token.value == "thickness" {
-                    self.consume(1);
-                    current_token = self.next();
-                    if current_token.is_none() {
-                        println!("[ERROR]: Missing value after 'thickness' keyword at line {}", token.line);
-                        exit(1);
-                    }
-                    let token = current_token.unwrap();
-                    if token.token_type == TokenType::FLOAT || token.token_type == TokenType::INTERGER {
-                        thickness = Some(token.value.parse::<f64>().unwrap());
-                    } else {
-                        println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                        println!("         > Expected a float or an integer");
-                        exit(1);
-                    }
-                }
-
-                if token.token_type == TokenType::KEYWORD && token.value == "step" {
-                    self.consume(1);
-                    current_token = self.next();
-                    if current_token.is_none() {
-                        println!("[ERROR]: Missing value after 'step' keyword at line {}", token.line);
-                        exit(1);
-                    }
-                    let token = current_token.unwrap();
-                    if token.token_type == TokenType::FLOAT || token.token_type == TokenType::INTERGER {
-                        let step_value = token.value.parse::<f64>().unwrap();
+                    },
+                    "thickness" => thickness = Some(self.get_number(&token)?),
+                    "step" | "xstep" | "ystep" => {
+                        let step_value = self.get_number(&token)?;
                         if step_value <= 0.0 {
-                            println!("[ERROR]: Step value must be greater than 0 at line {}", token.line);
-                            exit(1);
+                            return Err(
+                                Diagnostic::error(Code::InvalidValue, "Step value must be greater than 0")
+                                    .at(&token)
+                            );
                         }
-                        step = Some(step_value);
-                    } else {
-                        println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                        println!("         > Expected a float or an integer");
-                        exit(1);
-                    }
+                        if token.value != "ystep" {
+                            xstep = Some(step_value);
+                            keywords[0] = Some(token.clone());
+                        }
+                        if token.value != "xstep" {
+                            ystep = Some(step_value);
+                            keywords[1] = Some(token.clone());
+                        }
+                    },
+                    "minor" => {
+                        let minor_value = self.get_number(&token)?;
+                        if minor_value < 0.0 || minor_value.fract() != 0.0 {
+                            return Err(
+                                Diagnostic::error(Code::InvalidValue, "The number of minor lines must be a positive integer")
+                                    .at(&token)
+                            );
+                        }
+                        minor = Some(minor_value as usize);
+                        keywords[2] = Some(token.clone());
+                    },
+                    "dash" => dash = Some(self.get_dash(&token)?),
+                    _ => {
+                        return Err(self.unexpected_keyword(&token, &declaration, &["color", "alpha", "thickness", "step", "xstep", "ystep", "minor", "dash"]));
+                    },
                 }
-    
+
                 self.consume(1);
-                current_token = self.next();
             }
 
             if color.is_none() {
@@ -521,236 +1034,205 @@ impl Interpreter {
                 thickness = Some(1.0);
             }
 
-            if step.is_none() {
-                step = Some(1.0);
-            }
-
             let grid = Grid {
                 color: color.unwrap(),
                 alpha,
                 thickness,
-                step,
+                xstep,
+                ystep,
+                minor,
+                dash,
             };
 
             self.grid = Some(grid);
+            self.grid_keywords = Some((declaration.clone(), keywords));
+        } else if declaration.value == "legend" {
+            let mut position: Option<Position> = None;
+            let mut color: Option<String> = None;
+            let mut background: Option<String> = None;
+            let mut alpha: Option<f64> = None;
+            let mut fontsize: Option<f64> = None;
+
+            while let Some(token) = self.next_keyword(&declaration)? {
+                match token.value.as_str() {
+                    "position" => {
+                        let name = self.get_value(&token, vec![TokenType::STRING], "a string")?;
+                        position = Some(Position::parse(&name.value).ok_or(
+                            Diagnostic::error(Code::InvalidValue, format!("Unknown legend position '{}'", name.value))
+                                .at(&name)
+                                .with_note(format!("Expected one of the following: {}", Position::NAMES.join(", ")))
+                        )?);
+                    },
+                    "color" => color = Some(self.get_hex(&token)?),
+                    "background" => background = Some(self.get_hex(&token)?),
+                    "alpha" => {
+                        let alpha_value = self.get_number(&token)?;
+                        if !(0.0..=1.0).contains(&alpha_value) {
+                            return Err(
+                                Diagnostic::error(Code::InvalidValue, "Alpha value must be between 0 and 1")
+                                    .at(&token)
+                            );
+                        }
+                        alpha = Some(alpha_value);
+                    },
+                    "fontsize" => {
+                        let fontsize_value = self.get_number(&token)?;
+                        if fontsize_value <= 0.0 {
+                            return Err(
+                                Diagnostic::error(Code::InvalidValue, "Font size must be greater than 0")
+                                    .at(&token)
+                            );
+                        }
+                        fontsize = Some(fontsize_value);
+                    },
+                    _ => {
+                        return Err(self.unexpected_keyword(&token, &declaration, &["position", "color", "background", "alpha", "fontsize"]));
+                    },
+                }
+
+                self.consume(1);
+            }
+
+            let legend = Legend {
+                position: position.unwrap_or_default(),
+                color,
+                background,
+                alpha,
+                fontsize,
+            };
+
+            self.legend = Some(legend);
+        } else if declaration.value == "end" {
+            return Err(
+                Diagnostic::error(Code::UnexpectedToken, "Unexpected '#end'")
+                    .at(&declaration)
+                    .with_note("There is no declaration or function to close")
+            );
         } else {
-            println!("[ERROR]: Unknown declaration '{}' at line {}", declaration_name, current_token.unwrap().line);
-            exit(1);
+            return Err(
+                Diagnostic::error(Code::UnknownWord, format!("Unknown declaration '{}'", declaration.value))
+                    .at(&declaration)
+            );
         }
+
+        Ok(())
     }
 
-    fn process_function(&mut self) {
+    fn process_function(&mut self) -> Result<(), Diagnostic> {
         let func_name = self.next().unwrap();
         self.consume(1);
         match &func_name.value[..] {
             "line" => {
-                self.process_func_line();
+                self.process_func_line(&func_name)
             },
             "graph" => {
-                self.process_func_graph();
+                self.process_func_graph(&func_name)
             },
             "point" => {
-                self.process_func_point();
+                self.process_func_point(&func_name)
+            },
+            "text" => {
+                self.process_func_text(&func_name)
+            },
+            "rect" | "circle" | "polygon" => {
+                self.process_func_shape(&func_name)
+            },
+            "scatter" => {
+                self.process_func_scatter(&func_name)
+            },
+            "polyline" => {
+                self.process_func_polyline(&func_name)
+            },
+            "bars" => {
+                self.process_func_bars(&func_name)
+            },
+            "histogram" => {
+                self.process_func_histogram(&func_name)
             },
             _ => {
-                println!("[ERROR]: Unknown function '{}' at line {}", func_name.value, self.next().unwrap().line);
-                exit(1);
+                Err(
+                    Diagnostic::error(Code::UnknownWord, format!("Unknown function '{}'", func_name.value))
+                        .at(&func_name)
+                )
             },
         }
     }
 
-    fn get_tuple(&mut self, len: i32, allow_tokens: Vec<TokenType>, keyword_name: String) -> Vec<Token> {
-        if len == 0 {
-            return Vec::new();
-        }
-        let token_strings: Vec<String> = allow_tokens.iter().map(|x| self.lexer.get_human_readable(x.clone())).collect();
-        let mut tuple: Vec<Token> = Vec::new();
-        let mut current_token = self.next();
-        let mut pushed = 0;
-        for _ in 0..(len+ (len - 1)) {
-            if current_token.is_none() {
-                println!("[ERROR]: Missing values after '{keyword_name}'");
-                exit(1);
-            }
-            let token = current_token.unwrap();
-            let is_comma = token.token_type == TokenType::SYMBOL && token.value == ",";
-            
-            if is_comma {
-                self.consume(1);
-                current_token = self.next();
-                continue;
-            }
-
-            if !allow_tokens.contains(&token.token_type) {
-                if pushed != len {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected one of the following: {:?}", token_strings);
-                    exit(1);
-                }
-            } else {
-                tuple.push(token.clone());
-                pushed += 1;
-            }
-
-            self.consume(1);
-            current_token = self.next();
-        }
-
-        self.position -= 1;
-
-        return tuple;
-    }
-
     /// the line function has as arguments:
     /// - from (x, y)
     /// - to (x, y)
     /// - name? "string" -> optional
     /// - color? 0x000000 -> optional
     /// - thickness? 1 -> optional
-    fn process_func_line(&mut self) {
-        
+    /// - clip? true -> optional
+    fn process_func_line(&mut self, start: &Token) -> Result<(), Diagnostic> {
+
         let mut from: Option<(f64, f64)> = None;
         let mut to: Option<(f64, f64)> = None;
         let mut name: Option<String> = None;
         let mut color: Option<String> = None;
         let mut thickness: Option<f64> = None;
+        let mut clip: Option<bool> = None;
 
         let mut func = Function {
             name: "line".to_string(),
             args: Vec::new(),
         };
 
-        let mut current_token = self.next();
-
-        while current_token.is_some() {
-            let token = current_token.unwrap();
-            if token.token_type == TokenType::DECLARATION && token.value == "end" {
-                break;
-            }
-
-            if token.token_type != TokenType::KEYWORD {
-                println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                println!("         > Expected a keyword");
-                exit(1);
-            }
-
-            if token.token_type == TokenType::KEYWORD && token.value == "from" {
-                self.consume(1);
-                let values = self.get_tuple(
-                                 2, 
-                        vec![TokenType::FLOAT, TokenType::INTERGER],
-                        "from".to_string()
-                    );
-
-                from = Some(
-                    (
-                        values[0].value.parse::<f64>().unwrap(),
-                        values[1].value.parse::<f64>().unwrap(),
-                    )
-                );
-            }
-
-            if token.token_type == TokenType::KEYWORD && token.value == "to" {
-                self.consume(1);
-                let values = self.get_tuple(
-                                 2, 
-                        vec![TokenType::FLOAT, TokenType::INTERGER],
-                        "to".to_string()
-                    );
-
-                to = Some(
-                    (
-                        values[0].value.parse::<f64>().unwrap(),
-                        values[1].value.parse::<f64>().unwrap(),
-                    )
-                );
-            }
-
-            if token.token_type == TokenType::KEYWORD && token.value == "name" {
-                self.consume(1);
-                current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'name' keyword at line {}", token.line);
-                    exit(1);
-                }
-                let token = current_token.unwrap();
-                if token.token_type != TokenType::STRING {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a string");
-                    exit(1);
-                }
-                
-                name = Some(token.value);
-            }
-
-            if token.token_type == TokenType::KEYWORD && token.value == "color" {
-                self.consume(1);
-                current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'color' keyword at line {}", token.line);
-                    exit(1);
-                }
-                let token = current_token.unwrap();
-                if token.token_type != TokenType::HEX {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a hexadecimal value");
-                    exit(1);
-                }
-                
-                color = Some(token.value);
-            }
-
-            if token.token_type == TokenType::KEYWORD && token.value == "thickness" {
-                self.consume(1);
-                current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'thickness' keyword at line {}", token.line);
-                    exit(1);
-                }
-                let token = current_token.unwrap();
-                if token.token_type == TokenType::FLOAT || token.token_type == TokenType::INTERGER {
-                    thickness = Some(token.value.parse::<f64>().unwrap());
-                } else {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a float or an integer");
-                    exit(1);
-                }
+        while let Some(token) = self.next_keyword(start)? {
+            match token.value.as_str() {
+                "from" => from = Some(self.get_point(&token)?),
+                "to" => to = Some(self.get_point(&token)?),
+                "name" => name = Some(self.get_string(&token)?),
+                "color" => color = Some(self.get_hex(&token)?),
+                "thickness" => thickness = Some(self.get_number(&token)?),
+                "clip" => clip = Some(self.get_bool(&token)?),
+                _ => {
+                    return Err(self.unexpected_keyword(&token, start, &["from", "to", "name", "color", "thickness", "clip"]));
+                },
             }
 
             self.consume(1);
-            current_token = self.next();
         }
 
-        if from.is_none() {
-            println!("[ERROR]: Missing 'from' keyword");
-            println!("         > Need to specify a starting point for the line");
-            exit(1);
+        if let Some(from) = from {
+            func.args.push(Arg::From(from));
         } else {
-            func.args.push(Arg::From(from.unwrap()));
+            return Err(
+                Diagnostic::error(Code::MissingKeyword, "Missing 'from' keyword")
+                    .at(start)
+                    .with_note("Need to specify a starting point for the line")
+            );
         }
 
-        if to.is_none() {
-            println!("[ERROR]: Missing 'to' keyword");
-            println!("         > Need to specify an ending point for the line");
-            exit(1);
+        if let Some(to) = to {
+            func.args.push(Arg::To(to));
         } else {
-            func.args.push(Arg::To(to.unwrap()));
+            return Err(
+                Diagnostic::error(Code::MissingKeyword, "Missing 'to' keyword")
+                    .at(start)
+                    .with_note("Need to specify an ending point for the line")
+            );
         }
 
-        if name.is_some() {
-            func.args.push(Arg::Name(name.unwrap()));
+        if let Some(name) = name {
+            func.args.push(Arg::Name(name));
         }
 
-        if color.is_some() {
-            func.args.push(Arg::Color(color.unwrap()));
+        if let Some(color) = color {
+            func.args.push(Arg::Color(color));
         }
 
-        if thickness.is_some() {
-            func.args.push(Arg::Thickness(thickness.unwrap()));
+        if let Some(thickness) = thickness {
+            func.args.push(Arg::Thickness(thickness));
         }
 
-        self.functions.push(func);
+        if let Some(clip) = clip {
+            func.args.push(Arg::Clip(clip));
+        }
 
+        self.functions.push(func);
+        Ok(())
     }
 
     /// the graph function has as arguments:
@@ -758,450 +1240,1561 @@ impl Interpreter {
     /// - color? 0x000000
     /// - thickness? 1
     /// - function f(x)
-    fn process_func_graph(&mut self) {
+    /// - samples? 32 -> number of evenly spaced samples refined afterwards
+    /// - resolution? 0.5 -> maximum distance in svg units between the curve
+    ///   and the drawn path
+    /// - asymptotes? false -> marks the poles with dashed lines
+    /// - clip? true
+    fn process_func_graph(&mut self, start: &Token) -> Result<(), Diagnostic> {
         let mut name: Option<String> = None;
         let mut color: Option<String> = None;
         let mut thickness: Option<f64> = None;
-        let mut func: Option<String> = None;
+        let mut func: Option<Box<Expression>> = None;
+        let mut samples: Option<usize> = None;
+        let mut resolution: Option<f64> = None;
+        let mut asymptotes: Option<bool> = None;
+        let mut clip: Option<bool> = None;
 
         let mut function = Function {
             name: "graph".to_string(),
             args: Vec::new(),
         };
 
-        let mut current_token = self.next();
-
-        while current_token.is_some() {
-            let token = current_token.unwrap();
-            if token.token_type == TokenType::DECLARATION && token.value == "end" {
-                break;
-            }
-
-            if token.token_type != TokenType::KEYWORD {
-                println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                println!("         > Expected a keyword");
-                exit(1);
-            }
-
-            if token.token_type == TokenType::KEYWORD && token.value == "name" {
-                self.consume(1);
-                current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'name' keyword at line {}", token.line);
-                    exit(1);
-                }
-                let token = current_token.unwrap();
-                if token.token_type != TokenType::STRING {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a string");
-                    exit(1);
-                }
-                
-                name = Some(token.value);
-            }
-
-            if token.token_type == TokenType::KEYWORD && token.value == "color" {
-                self.consume(1);
-                current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'color' keyword at line {}", token.line);
-                    exit(1);
-                }
-                let token = current_token.unwrap();
-                if token.token_type != TokenType::HEX {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a hexadecimal value");
-                    exit(1);
-                }
-                
-                color = Some(token.value);
-            }
-
-            if token.token_type == TokenType::KEYWORD && token.value == "thickness" {
-                self.consume(1);
-                current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'thickness' keyword at line {}", token.line);
-                    exit(1);
-                }
-                let token = current_token.unwrap();
-                if token.token_type == TokenType::FLOAT || token.token_type == TokenType::INTERGER {
-                    thickness = Some(token.value.parse::<f64>().unwrap());
-                } else {                    
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a float or an integer");
-                    exit(1);
-                }
+        while let Some(token) = self.next_keyword(start)? {
+            match token.value.as_str() {
+                "name" => name = Some(self.get_string(&token)?),
+                "color" => color = Some(self.get_hex(&token)?),
+                "thickness" => thickness = Some(self.get_number(&token)?),
+                "func" => func = Some(Box::new(self.get_expression(&token)?)),
+                "samples" => {
+                    let samples_value = self.get_number(&token)?;
+                    if samples_value < 2.0 || samples_value.fract() != 0.0 {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "The number of samples must be an integer greater than 1")
+                                .at(&token)
+                        );
+                    }
+                    samples = Some(samples_value as usize);
+                },
+                "resolution" => {
+                    let resolution_value = self.get_number(&token)?;
+                    if resolution_value <= 0.0 {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Resolution value must be greater than 0")
+                                .at(&token)
+                        );
+                    }
+                    resolution = Some(resolution_value);
+                },
+                "asymptotes" => asymptotes = Some(self.get_bool(&token)?),
+                "clip" => clip = Some(self.get_bool(&token)?),
+                _ => {
+                    return Err(self.unexpected_keyword(&token, start, &["name", "color", "thickness", "func", "samples", "resolution", "asymptotes", "clip"]));
+                },
             }
 
-            if token.token_type == TokenType::KEYWORD && token.value == "func" {
-                self.consume(1);
-                current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'function' keyword at line {}", token.line);
-                    exit(1);
-                }
+            self.consume(1);
+        }
 
-                let token = current_token.unwrap();
+        if let Some(name) = name {
+            function.args.push(Arg::Name(name));
+        }
 
-                if token.token_type != TokenType::STRING {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a string");
-                    exit(1);
-                }
+        if let Some(color) = color {
+            function.args.push(Arg::Color(color));
+        }
 
-                func = Some(token.value);
-            }
+        if let Some(thickness) = thickness {
+            function.args.push(Arg::Thickness(thickness));
+        }
 
-            self.consume(1);
-            current_token = self.next();
+        if let Some(samples) = samples {
+            function.args.push(Arg::Samples(samples));
         }
 
-        if name.is_some() {
-            function.args.push(Arg::Name(name.unwrap()));
+        if let Some(resolution) = resolution {
+            function.args.push(Arg::Resolution(resolution));
         }
 
-        if color.is_some() {
-            function.args.push(Arg::Color(color.unwrap()));
+        if let Some(asymptotes) = asymptotes {
+            function.args.push(Arg::Asymptotes(asymptotes));
         }
 
-        if thickness.is_some() {
-            function.args.push(Arg::Thickness(thickness.unwrap()));
+        if let Some(clip) = clip {
+            function.args.push(Arg::Clip(clip));
         }
 
-        if func.is_none() {
-            println!("[ERROR]: Missing 'func' keyword");
-            println!("         > Need to specify a function");
-            exit(1);
+        if let Some(func) = func {
+            function.args.push(Arg::Func(func));
         } else {
-            function.args.push(Arg::Func(func.unwrap()));
+            return Err(
+                Diagnostic::error(Code::MissingKeyword, "Missing 'func' keyword")
+                    .at(start)
+                    .with_note("Need to specify a function")
+            );
         }
 
         self.functions.push(function);
-
+        Ok(())
     }
 
     /// the point function has as arguments:
     /// - at (x, y)
     /// - name? "string"
     /// - color? 0x000000
-    fn process_func_point(&mut self) {
+    /// - clip? true
+    fn process_func_point(&mut self, start: &Token) -> Result<(), Diagnostic> {
 
         let mut at: Option<(f64, f64)> = None;
         let mut name: Option<String> = None;
         let mut color: Option<String> = None;
+        let mut clip: Option<bool> = None;
 
         let mut func = Function {
             name: "point".to_string(),
             args: Vec::new(),
         };
 
-        let mut current_token = self.next();
-
-        while current_token.is_some() {
-
-            let token = current_token.unwrap();
-            if token.token_type == TokenType::DECLARATION && token.value == "end" {
-                break;
-            }
-
-            if token.token_type != TokenType::KEYWORD {
-                println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                println!("         > Expected a keyword");
-                exit(1);
-            }
-
-            if token.token_type == TokenType::KEYWORD && token.value == "at" {
-                self.consume(1);
-                let values = self.get_tuple(
-                                 2, 
-                        vec![TokenType::FLOAT, TokenType::INTERGER],
-                        "at".to_string()
-                    );
-
-                at = Some(
-                    (
-                        values[0].value.parse::<f64>().unwrap(),
-                        values[1].value.parse::<f64>().unwrap(),
-                    )
-                );
-            }
-
-            if token.token_type == TokenType::KEYWORD && token.value == "name" {
-                self.consume(1);
-                current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'name' keyword at line {}", token.line);
-                    exit(1);
-                }
-                let token = current_token.unwrap();
-                if token.token_type != TokenType::STRING {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a string");
-                    exit(1);
-                }
-                
-                name = Some(token.value);
-            }
-
-            if token.token_type == TokenType::KEYWORD && token.value == "color" {
-                self.consume(1);
-                current_token = self.next();
-                if current_token.is_none() {
-                    println!("[ERROR]: Missing value after 'color' keyword at line {}", token.line);
-                    exit(1);
-                }
-                let token = current_token.unwrap();
-                if token.token_type != TokenType::HEX {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Expected a hexadecimal value");
-                    exit(1);
-                }
-                
-                color = Some(token.value);
+        while let Some(token) = self.next_keyword(start)? {
+            match token.value.as_str() {
+                "at" => at = Some(self.get_point(&token)?),
+                "name" => name = Some(self.get_string(&token)?),
+                "color" => color = Some(self.get_hex(&token)?),
+                "clip" => clip = Some(self.get_bool(&token)?),
+                _ => {
+                    return Err(self.unexpected_keyword(&token, start, &["at", "name", "color", "clip"]));
+                },
             }
 
             self.consume(1);
-            current_token = self.next();
         }
 
-        if at.is_none() {
-            println!("[ERROR]: Missing 'at' keyword");
-            println!("         > Need to specify a point");
-            exit(1);
+        if let Some(at) = at {
+            func.args.push(Arg::At(at));
         } else {
-            func.args.push(Arg::At(at.unwrap()));
+            return Err(
+                Diagnostic::error(Code::MissingKeyword, "Missing 'at' keyword")
+                    .at(start)
+                    .with_note("Need to specify a point")
+            );
         }
 
-        if name.is_some() {
-            func.args.push(Arg::Name(name.unwrap()));
+        if let Some(name) = name {
+            func.args.push(Arg::Name(name));
         }
 
-        if color.is_some() {
-            func.args.push(Arg::Color(color.unwrap()));
+        if let Some(color) = color {
+            func.args.push(Arg::Color(color));
         }
 
-        self.functions.push(func);
+        if let Some(clip) = clip {
+            func.args.push(Arg::Clip(clip));
+        }
 
+        self.functions.push(func);
+        Ok(())
     }
 
+    /// the text function has as arguments:
+    /// - at (x, y)
+    /// - content "string"
+    /// - name? "string"
+    /// - color? 0x000000
+    /// - size? 10 -> font size
+    /// - anchor? "start" -> "start", "middle" or "end" of the text at `at`
+    /// - rotate? 0 -> angle in degrees, counterclockwise
+    /// - space? "data" -> "pixel" for `at` in svg units
+    /// - clip? true -> false by default in the "pixel" space
+    fn process_func_text(&mut self, start: &Token) -> Result<(), Diagnostic> {
 
-    fn preprocess(&mut self) {
-        self.check_declarations();
-        self.check_root();
-    }
+        let mut at: Option<(f64, f64)> = None;
+        let mut content: Option<String> = None;
+        let mut name: Option<String> = None;
+        let mut color: Option<String> = None;
+        let mut size: Option<f64> = None;
+        let mut anchor: Option<String> = None;
+        let mut rotate: Option<f64> = None;
+        let mut pixel: Option<bool> = None;
+        let mut clip: Option<bool> = None;
 
-    pub fn compile(&mut self) {
-        self.preprocess();
-        // println!("TOKENS\n{:?}\n\n", self.tokens);
-        let mut current_token = self.next();
-        while current_token.is_some() {
-            let token = current_token.unwrap();
-            match token.token_type {
-                TokenType::STRING   | 
-                TokenType::INTERGER | 
-                TokenType::FLOAT    | 
-                TokenType::HEX      | 
-                TokenType::SYMBOL   | 
-                TokenType::KEYWORD  | 
-                TokenType::VARNAME  | 
-                TokenType::VAR => {
-                    println!("[ERROR]: Unexpected token '{}' at line {}", token.value, token.line);
-                    println!("         > Only declarations, definitions and functions are allowed at the top level");
-                    exit(1);
-                },
+        let mut func = Function {
+            name: "text".to_string(),
+            args: Vec::new(),
+        };
 
-                TokenType::DEFINE => {
-                    self.process_define();
+        while let Some(token) = self.next_keyword(start)? {
+            match token.value.as_str() {
+                "at" => at = Some(self.get_point(&token)?),
+                "content" => content = Some(self.get_string(&token)?),
+                "name" => name = Some(self.get_string(&token)?),
+                "color" => color = Some(self.get_hex(&token)?),
+                "size" => {
+                    let size_value = self.get_number(&token)?;
+                    if size_value <= 0.0 {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Size value must be greater than 0")
+                                .at(&token)
+                        );
+                    }
+                    size = Some(size_value);
                 },
-                TokenType::DECLARATION => {
-                    self.process_declaration();
+                "anchor" => {
+                    let anchor_value = self.get_value(&token, vec![TokenType::STRING], "a string")?;
+                    if !["start", "middle", "end"].contains(&anchor_value.value.as_str()) {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, format!("Unknown anchor '{}'", anchor_value.value))
+                                .at(&anchor_value)
+                                .with_help("The anchor is one of 'start', 'middle' or 'end'")
+                        );
+                    }
+                    anchor = Some(anchor_value.value);
+                },
+                "rotate" => rotate = Some(self.get_number(&token)?),
+                "space" => {
+                    let space_value = self.get_value(&token, vec![TokenType::STRING], "a string")?;
+                    if !["data", "pixel"].contains(&space_value.value.as_str()) {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, format!("Unknown space '{}'", space_value.value))
+                                .at(&space_value)
+                                .with_help("The space is 'data' for coordinates on the axes, or 'pixel' for svg units")
+                        );
+                    }
+                    pixel = Some(space_value.value == "pixel");
+                },
+                "clip" => clip = Some(self.get_bool(&token)?),
+                _ => {
+                    return Err(self.unexpected_keyword(&token, start, &["at", "content", "name", "color", "size", "anchor", "rotate", "space", "clip"]));
                 },
-                TokenType::FUNCTION => {
-                    self.process_function();
-                },    
             }
 
             self.consume(1);
-            current_token = self.next();
         }
-        
-        // println!("Definitions: {:?}", self.definitions);
-        // println!("Root: {:?}", self.root);
-        // println!("Grid: {:?}", self.grid);
-        // println!("Functions: {:?}", self.functions);
-        println!("{}", self.gen_svg().as_str());
-    }
 
+        if let Some(at) = at {
+            func.args.push(Arg::At(at));
+        } else {
+            return Err(
+                Diagnostic::error(Code::MissingKeyword, "Missing 'at' keyword")
+                    .at(start)
+                    .with_note("Need to specify where the text is written")
+            );
+        }
 
-    //// Functions for the generation of the SVG string
-    fn gen_svg(&mut self) -> String {
-        let mut svg = String::new();
-        svg.push_str(
-            &format!(
-                "<svg viewBox=\"{} {} {} {}\" xmlns=\"http://www.w3.org/2000/svg\">\n", 
-                self.root.as_ref().unwrap()._box.0,
-                self.root.as_ref().unwrap()._box.1,
-                self.root.as_ref().unwrap()._box.2 + 10.0, 
-                self.root.as_ref().unwrap()._box.3 + 10.0,
-            )
-        );
+        if let Some(content) = content {
+            func.args.push(Arg::Content(content));
+        } else {
+            return Err(
+                Diagnostic::error(Code::MissingKeyword, "Missing 'content' keyword")
+                    .at(start)
+                    .with_note("Need to specify the text to write")
+            );
+        }
 
-        svg.push_str(
-            &format!(
-                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#{}\"/>\n", 
-                self.root.as_ref().unwrap()._box.0,
-                self.root.as_ref().unwrap()._box.1,
-                self.root.as_ref().unwrap()._box.2 + 10.0, 
-                self.root.as_ref().unwrap()._box.3 + 10.0,
-                self.root.as_ref().unwrap().background
-            )
-        );
+        if let Some(name) = name {
+            func.args.push(Arg::Name(name));
+        }
 
-        if self.grid.is_some() {
-            svg.push_str(gen_grid(
-                self.grid.clone().unwrap(),
-                self.root.as_ref().unwrap()._box.2 + 10.0,
-                self.root.as_ref().unwrap()._box.3 + 10.0,
-            ).as_str())
+        if let Some(color) = color {
+            func.args.push(Arg::Color(color));
         }
 
-        // root axis
-        svg.push_str(draw_axis(
-            self.root.clone().unwrap().axis.0,
-            self.root.clone().unwrap().axis.1,
-            self.root.as_ref().unwrap()._box.2,
-            self.root.as_ref().unwrap()._box.3,
-            self.root.as_ref().unwrap().color.clone(),
-        ).as_str());
+        if let Some(size) = size {
+            func.args.push(Arg::Size(size));
+        }
 
-        for function in self.functions.iter() {
-            let func = gen_function(
-                function.clone(), 
-                self.root.as_ref().unwrap()._box.2, 
-                self.root.as_ref().unwrap()._box.3
-            );
-            svg.push_str(func.as_str());
+        if let Some(anchor) = anchor {
+            func.args.push(Arg::Anchor(anchor));
         }
 
+        if let Some(rotate) = rotate {
+            func.args.push(Arg::Rotate(rotate));
+        }
+
+        if let Some(pixel) = pixel {
+            func.args.push(Arg::Pixel(pixel));
+        }
+
+        // a text placed in svg units is usually outside of the plot area
+        func.args.push(Arg::Clip(clip.unwrap_or(pixel != Some(true))));
+
+        self.functions.push(func);
+        Ok(())
+    }
+
+    /// the shape functions have as arguments:
+    /// - from (x, y) and to (x, y) -> opposite corners of a rect
+    /// - at (x, y) and radius r or rx, ry -> center and radii of a circle
+    /// - points (x, y), (x, y), ... -> corners of a polygon
+    /// - name? "string"
+    /// - color? 0x000000 -> color of the outline
+    /// - fill? 0x000000 -> color of the inside, not filled by default
+    /// - alpha? 1 -> opacity of the inside
+    /// - thickness? 1
+    /// - clip? true
+    fn process_func_shape(&mut self, start: &Token) -> Result<(), Diagnostic> {
+
+        let mut from: Option<(f64, f64)> = None;
+        let mut to: Option<(f64, f64)> = None;
+        let mut at: Option<(f64, f64)> = None;
+        let mut radius: Option<(f64, f64)> = None;
+        let mut points: Option<(Vec<(f64, f64)>, Token)> = None;
+        let mut name: Option<String> = None;
+        let mut color: Option<String> = None;
+        let mut fill: Option<String> = None;
+        let mut alpha: Option<f64> = None;
+        let mut thickness: Option<f64> = None;
+        let mut clip: Option<bool> = None;
+
+        let mut func = Function {
+            name: start.value.clone(),
+            args: Vec::new(),
+        };
+
+        let allowed: &[&str] = match start.value.as_str() {
+            "rect" => &["from", "to", "name", "color", "fill", "alpha", "thickness", "clip"],
+            "circle" => &["at", "radius", "name", "color", "fill", "alpha", "thickness", "clip"],
+            _ => &["points", "name", "color", "fill", "alpha", "thickness", "clip"],
+        };
+
+        while let Some(token) = self.next_keyword(start)? {
+            if !allowed.contains(&token.value.as_str()) {
+                return Err(self.unexpected_keyword(&token, start, allowed));
+            }
+            match token.value.as_str() {
+                "from" => from = Some(self.get_point(&token)?),
+                "to" => to = Some(self.get_point(&token)?),
+                "at" => at = Some(self.get_point(&token)?),
+                "radius" => radius = Some(self.get_radius(&token)?),
+                "points" => points = Some((self.get_points(&token)?, token)),
+                "name" => name = Some(self.get_string(&token)?),
+                "color" => color = Some(self.get_hex(&token)?),
+                "fill" => fill = Some(self.get_hex(&token)?),
+                "alpha" => {
+                    let alpha_value = self.get_number(&token)?;
+                    if !(0.0..=1.0).contains(&alpha_value) {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Alpha value must be between 0 and 1")
+                                .at(&token)
+                        );
+                    }
+                    alpha = Some(alpha_value);
+                },
+                "thickness" => thickness = Some(self.get_number(&token)?),
+                "clip" => clip = Some(self.get_bool(&token)?),
+                _ => unreachable!("the keywords are checked against the allowed ones"),
+            }
+
+            self.consume(1);
+        }
+
+        let missing = |keyword: &str, note: &str| {
+            Diagnostic::error(Code::MissingKeyword, format!("Missing '{}' keyword", keyword))
+                .at(start)
+                .with_note(note.to_string())
+        };
+        match start.value.as_str() {
+            "rect" => {
+                func.args.push(Arg::From(from.ok_or(missing("from", "Need to specify a corner of the rect"))?));
+                func.args.push(Arg::To(to.ok_or(missing("to", "Need to specify the corner of the rect opposite to 'from'"))?));
+            },
+            "circle" => {
+                func.args.push(Arg::At(at.ok_or(missing("at", "Need to specify the center of the circle"))?));
+                func.args.push(Arg::Radius(radius.ok_or(missing("radius", "Need to specify the radius of the circle"))?));
+            },
+            _ => {
+                let (points, token) = points.ok_or(missing("points", "Need to specify the corners of the polygon"))?;
+                if points.len() < 3 {
+                    return Err(
+                        Diagnostic::error(Code::InvalidValue, "A polygon needs at least 3 points")
+                            .at(&token)
+                            .with_note(format!("Found {} points", points.len()))
+                    );
+                }
+                func.args.push(Arg::Points(points));
+            },
+        }
+
+        if let Some(name) = name {
+            func.args.push(Arg::Name(name));
+        }
+
+        if let Some(color) = color {
+            func.args.push(Arg::Color(color));
+        }
+
+        if let Some(fill) = fill {
+            func.args.push(Arg::Fill(fill));
+        }
+
+        if let Some(alpha) = alpha {
+            func.args.push(Arg::Alpha(alpha));
+        }
+
+        if let Some(thickness) = thickness {
+            func.args.push(Arg::Thickness(thickness));
+        }
+
+        if let Some(clip) = clip {
+            func.args.push(Arg::Clip(clip));
+        }
+
+        self.functions.push(func);
+        Ok(())
+    }
+
+    /// the scatter function has as arguments:
+    /// - points (x, y), (x, y), ... -> repeated to add more points
+    /// - data? "file.csv" -> points read from a csv file
+    /// - columns? 1, 2 -> x and y columns of the file, by name or position
+    /// - header? true -> whether the first row of the file names the columns
+    /// - name? "string"
+    /// - marker? "circle" -> shape drawn at every point
+    /// - size? 4 -> width of the markers
+    /// - color? 0x000000
+    /// - alpha? 1
+    /// - clip? true
+    fn process_func_scatter(&mut self, start: &Token) -> Result<(), Diagnostic> {
+
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        let mut name: Option<String> = None;
+        let mut marker: Option<String> = None;
+        let mut size: Option<f64> = None;
+        let mut color: Option<String> = None;
+        let mut alpha: Option<f64> = None;
+        let mut clip: Option<bool> = None;
+        let mut data: Option<Token> = None;
+        let mut columns: Option<(Column, Column)> = None;
+        let mut header: Option<bool> = None;
+        // a 'columns' or 'header' keyword, which needs a file
+        let mut data_option: Option<Token> = None;
+
+        let mut func = Function {
+            name: "scatter".to_string(),
+            args: Vec::new(),
+        };
+
+        while let Some(token) = self.next_keyword(start)? {
+            match token.value.as_str() {
+                "points" => points.extend(self.get_points(&token)?),
+                "data" => data = Some(self.get_value(&token, vec![TokenType::STRING], "a string")?),
+                "columns" => {
+                    columns = Some(self.get_columns(&token)?);
+                    data_option = Some(token.clone());
+                },
+                "header" => {
+                    header = Some(self.get_bool(&token)?);
+                    data_option = Some(token.clone());
+                },
+                "name" => name = Some(self.get_string(&token)?),
+                "marker" => {
+                    let marker_value = self.get_value(&token, vec![TokenType::STRING], "a string")?;
+                    if !MARKERS.contains(&marker_value.value.as_str()) {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, format!("Unknown marker '{}'", marker_value.value))
+                                .at(&marker_value)
+                                .with_note(format!("Expected one of the following: {}", MARKERS.join(", ")))
+                        );
+                    }
+                    marker = Some(marker_value.value);
+                },
+                "size" => {
+                    let size_value = self.get_number(&token)?;
+                    if size_value <= 0.0 {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Size value must be greater than 0")
+                                .at(&token)
+                        );
+                    }
+                    size = Some(size_value);
+                },
+                "color" => color = Some(self.get_hex(&token)?),
+                "alpha" => {
+                    let alpha_value = self.get_number(&token)?;
+                    if !(0.0..=1.0).contains(&alpha_value) {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Alpha value must be between 0 and 1")
+                                .at(&token)
+                        );
+                    }
+                    alpha = Some(alpha_value);
+                },
+                "clip" => clip = Some(self.get_bool(&token)?),
+                _ => {
+                    return Err(self.unexpected_keyword(&token, start, &["points", "data", "columns", "header", "name", "marker", "size", "color", "alpha", "clip"]));
+                },
+            }
+
+            self.consume(1);
+        }
+
+        points.extend(self.load_points(data.as_ref(), columns, header, data_option.as_ref())?);
+        if points.is_empty() {
+            return Err(
+                Diagnostic::error(Code::MissingKeyword, "Missing 'points' keyword")
+                    .at(start)
+                    .with_note("Need to specify the points of the scatter, or a 'data' file")
+            );
+        }
+        func.args.push(Arg::Points(points));
+
+        if let Some(name) = name {
+            func.args.push(Arg::Name(name));
+        }
+
+        if let Some(marker) = marker {
+            func.args.push(Arg::Marker(marker));
+        }
+
+        if let Some(size) = size {
+            func.args.push(Arg::Size(size));
+        }
+
+        if let Some(color) = color {
+            func.args.push(Arg::Color(color));
+        }
+
+        if let Some(alpha) = alpha {
+            func.args.push(Arg::Alpha(alpha));
+        }
+
+        if let Some(clip) = clip {
+            func.args.push(Arg::Clip(clip));
+        }
+
+        self.functions.push(func);
+        Ok(())
+    }
+
+
+    /// the polyline function has as arguments:
+    /// - points (x, y), (x, y), ... -> repeated to add more points
+    /// - data? "file.csv" -> points read from a csv file, after the others
+    /// - columns? 1, 2 -> x and y columns of the file, by name or position
+    /// - header? true -> whether the first row of the file names the columns
+    /// - curve? "linear" -> how the points are joined
+    /// - name? "string"
+    /// - color? 0x000000
+    /// - thickness? 1
+    /// - dash? "4 2"
+    /// - clip? true
+    fn process_func_polyline(&mut self, start: &Token) -> Result<(), Diagnostic> {
+
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        let mut data: Option<Token> = None;
+        let mut columns: Option<(Column, Column)> = None;
+        let mut header: Option<bool> = None;
+        // a 'columns' or 'header' keyword, which needs a file
+        let mut data_option: Option<Token> = None;
+        let mut curve: Option<String> = None;
+        let mut name: Option<String> = None;
+        let mut color: Option<String> = None;
+        let mut thickness: Option<f64> = None;
+        let mut dash: Option<String> = None;
+        let mut clip: Option<bool> = None;
+
+        let mut func = Function {
+            name: "polyline".to_string(),
+            args: Vec::new(),
+        };
+
+        while let Some(token) = self.next_keyword(start)? {
+            match token.value.as_str() {
+                "points" => points.extend(self.get_points(&token)?),
+                "data" => data = Some(self.get_value(&token, vec![TokenType::STRING], "a string")?),
+                "columns" => {
+                    columns = Some(self.get_columns(&token)?);
+                    data_option = Some(token.clone());
+                },
+                "header" => {
+                    header = Some(self.get_bool(&token)?);
+                    data_option = Some(token.clone());
+                },
+                "curve" => {
+                    let curve_value = self.get_value(&token, vec![TokenType::STRING], "a string")?;
+                    if !CURVES.contains(&curve_value.value.as_str()) {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, format!("Unknown curve '{}'", curve_value.value))
+                                .at(&curve_value)
+                                .with_note(format!("Expected one of the following: {}", CURVES.join(", ")))
+                        );
+                    }
+                    curve = Some(curve_value.value);
+                },
+                "name" => name = Some(self.get_string(&token)?),
+                "color" => color = Some(self.get_hex(&token)?),
+                "thickness" => thickness = Some(self.get_number(&token)?),
+                "dash" => dash = Some(self.get_dash(&token)?),
+                "clip" => clip = Some(self.get_bool(&token)?),
+                _ => {
+                    return Err(self.unexpected_keyword(&token, start, &["points", "data", "columns", "header", "curve", "name", "color", "thickness", "dash", "clip"]));
+                },
+            }
+
+            self.consume(1);
+        }
+
+        points.extend(self.load_points(data.as_ref(), columns, header, data_option.as_ref())?);
+        if points.is_empty() {
+            return Err(
+                Diagnostic::error(Code::MissingKeyword, "Missing 'points' keyword")
+                    .at(start)
+                    .with_note("Need to specify the points of the polyline, or a 'data' file")
+            );
+        }
+        if points.len() < 2 {
+            return Err(
+                Diagnostic::error(Code::InvalidValue, "A polyline needs at least 2 points")
+                    .at(start)
+                    .with_note(format!("Found {} point", points.len()))
+            );
+        }
+        func.args.push(Arg::Points(points));
+
+        if let Some(curve) = curve {
+            func.args.push(Arg::Curve(curve));
+        }
+
+        if let Some(name) = name {
+            func.args.push(Arg::Name(name));
+        }
+
+        if let Some(color) = color {
+            func.args.push(Arg::Color(color));
+        }
+
+        if let Some(thickness) = thickness {
+            func.args.push(Arg::Thickness(thickness));
+        }
+
+        if let Some(dash) = dash {
+            func.args.push(Arg::Dash(dash));
+        }
+
+        if let Some(clip) = clip {
+            func.args.push(Arg::Clip(clip));
+        }
+
+        self.functions.push(func);
+        Ok(())
+    }
+
+
+    /// the bars function has as arguments:
+    /// - values 3, 5, 2 -> heights of the bars at 1, 2, 3, ..., where the
+    ///   categories of an axis are
+    /// - points? (x, height), ... -> bars at other positions
+    /// - data? "file.csv" -> positions and heights read from a csv file
+    /// - columns? 1, 2 -> position and height columns of the file
+    /// - header? true -> whether the first row of the file names the columns
+    /// - mode? "grouped" -> bars side by side with the other grouped ones,
+    ///   or "stacked" on top of the other stacked ones
+    /// - width? -> width of the bars, or of a group of bars
+    /// - name? "string"
+    /// - color? 0x000000
+    /// - colors? 0x000000, ... -> colors of the bars in turn
+    /// - alpha? 1
+    /// - clip? true
+    fn process_func_bars(&mut self, start: &Token) -> Result<(), Diagnostic> {
+
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        // 'values' are placed at 1, 2, 3... whatever the 'points' around them
+        let mut value_count = 0;
+        let mut data: Option<Token> = None;
+        let mut columns: Option<(Column, Column)> = None;
+        let mut header: Option<bool> = None;
+        // a 'columns' or 'header' keyword, which needs a file
+        let mut data_option: Option<Token> = None;
+        let mut mode: Option<String> = None;
+        let mut width: Option<f64> = None;
+        let mut name: Option<String> = None;
+        let mut color: Option<String> = None;
+        let mut colors: Option<Vec<String>> = None;
+        let mut alpha: Option<f64> = None;
+        let mut clip: Option<bool> = None;
+
+        let mut func = Function {
+            name: "bars".to_string(),
+            args: Vec::new(),
+        };
+
+        while let Some(token) = self.next_keyword(start)? {
+            match token.value.as_str() {
+                "values" => {
+                    let values = self.get_list(&token, vec![TokenType::FLOAT, TokenType::INTERGER], "a float or an integer")?;
+                    for value in values.iter() {
+                        value_count += 1;
+                        points.push((value_count as f64, parse_number(value)?));
+                    }
+                },
+                "points" => points.extend(self.get_points(&token)?),
+                "data" => data = Some(self.get_value(&token, vec![TokenType::STRING], "a string")?),
+                "columns" => {
+                    columns = Some(self.get_columns(&token)?);
+                    data_option = Some(token.clone());
+                },
+                "header" => {
+                    header = Some(self.get_bool(&token)?);
+                    data_option = Some(token.clone());
+                },
+                "mode" => {
+                    let mode_value = self.get_value(&token, vec![TokenType::STRING], "a string")?;
+                    if !["grouped", "stacked"].contains(&mode_value.value.as_str()) {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, format!("Unknown mode '{}'", mode_value.value))
+                                .at(&mode_value)
+                                .with_help("The mode is one of 'grouped' or 'stacked'")
+                        );
+                    }
+                    mode = Some(mode_value.value);
+                },
+                "width" => {
+                    let width_value = self.get_number(&token)?;
+                    if width_value <= 0.0 {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Width value must be greater than 0")
+                                .at(&token)
+                        );
+                    }
+                    width = Some(width_value);
+                },
+                "name" => name = Some(self.get_string(&token)?),
+                "color" => color = Some(self.get_hex(&token)?),
+                "colors" => {
+                    let values = self.get_list(&token, vec![TokenType::HEX], "a hexadecimal value")?;
+                    colors = Some(values.into_iter().map(parse_color).collect::<Result<Vec<String>, Diagnostic>>()?);
+                },
+                "alpha" => {
+                    let alpha_value = self.get_number(&token)?;
+                    if !(0.0..=1.0).contains(&alpha_value) {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Alpha value must be between 0 and 1")
+                                .at(&token)
+                        );
+                    }
+                    alpha = Some(alpha_value);
+                },
+                "clip" => clip = Some(self.get_bool(&token)?),
+                _ => {
+                    return Err(self.unexpected_keyword(&token, start, &["values", "points", "data", "columns", "header", "mode", "width", "name", "color", "colors", "alpha", "clip"]));
+                },
+            }
+
+            self.consume(1);
+        }
+
+        points.extend(self.load_points(data.as_ref(), columns, header, data_option.as_ref())?);
+        if points.is_empty() {
+            return Err(
+                Diagnostic::error(Code::MissingKeyword, "Missing 'values' keyword")
+                    .at(start)
+                    .with_note("Need to specify the heights of the bars, as 'values', 'points' or a 'data' file")
+            );
+        }
+        func.args.push(Arg::Points(points));
+
+        if let Some(mode) = mode {
+            func.args.push(Arg::Mode(mode));
+        }
+
+        if let Some(width) = width {
+            func.args.push(Arg::Width(width));
+        }
+
+        if let Some(name) = name {
+            func.args.push(Arg::Name(name));
+        }
+
+        if let Some(color) = color {
+            func.args.push(Arg::Color(color));
+        }
+
+        if let Some(colors) = colors {
+            func.args.push(Arg::Colors(colors));
+        }
+
+        if let Some(alpha) = alpha {
+            func.args.push(Arg::Alpha(alpha));
+        }
+
+        if let Some(clip) = clip {
+            func.args.push(Arg::Clip(clip));
+        }
+
+        self.functions.push(func);
+        Ok(())
+    }
+
+    /// the histogram function has as arguments:
+    /// - values 1.2, 3.4, ... -> repeated to add more values
+    /// - data? "file.csv" -> values read from a csv file
+    /// - column? 1 -> column of the file, by name or position
+    /// - header? true -> whether the first row of the file names the columns
+    /// - bins? "sturges" -> number of bins, or "sturges" or "fd" rule
+    /// - width? -> width of the bins, instead of their number
+    /// - density? false -> heights normalized so the bins have an area of 1
+    /// - name? "string"
+    /// - color? 0x000000
+    /// - alpha? 1
+    /// - clip? true
+    fn process_func_histogram(&mut self, start: &Token) -> Result<(), Diagnostic> {
+
+        let mut values: Vec<f64> = Vec::new();
+        let mut data: Option<Token> = None;
+        let mut column: Option<Column> = None;
+        let mut header: Option<bool> = None;
+        // a 'column' or 'header' keyword, which needs a file
+        let mut data_option: Option<Token> = None;
+        let mut binning: Option<(Binning, Token)> = None;
+        let mut density: Option<bool> = None;
+        let mut name: Option<String> = None;
+        let mut color: Option<String> = None;
+        let mut alpha: Option<f64> = None;
+        let mut clip: Option<bool> = None;
+
+        let mut func = Function {
+            name: "histogram".to_string(),
+            args: Vec::new(),
+        };
+
+        while let Some(token) = self.next_keyword(start)? {
+            match token.value.as_str() {
+                "values" => {
+                    for value in self.get_list(&token, vec![TokenType::FLOAT, TokenType::INTERGER], "a float or an integer")?.iter() {
+                        values.push(parse_number(value)?);
+                    }
+                },
+                "data" => data = Some(self.get_value(&token, vec![TokenType::STRING], "a string")?),
+                "column" => {
+                    let column_value = self.get_value(&token, vec![TokenType::STRING, TokenType::INTERGER], "a string or an integer")?;
+                    column = Some(match column_value.token_type {
+                        TokenType::STRING => Column::Name(column_value.value),
+                        _ => match column_value.value.parse::<usize>() {
+                            Ok(index) if index > 0 => Column::Index(index),
+                            _ => {
+                                return Err(
+                                    Diagnostic::error(Code::InvalidValue, format!("Invalid column {}", column_value.value))
+                                        .at(&column_value)
+                                        .with_note("The first column is 1")
+                                );
+                            },
+                        },
+                    });
+                    data_option = Some(token.clone());
+                },
+                "header" => {
+                    header = Some(self.get_bool(&token)?);
+                    data_option = Some(token.clone());
+                },
+                "bins" | "width" if binning.as_ref().is_some_and(|(_, previous)| previous.value != token.value) => {
+                    return Err(
+                        Diagnostic::error(Code::InvalidValue, format!("'{}' is set along with another way of binning", token.value))
+                            .at(&token)
+                            .with_help("Give either the number of bins, a rule or the width of the bins")
+                    );
+                },
+                "bins" => {
+                    let bins_value = self.get_value(&token, vec![TokenType::INTERGER, TokenType::STRING], "an integer or a string")?;
+                    let bins = match bins_value.token_type {
+                        TokenType::STRING => Binning::parse(&bins_value.value).ok_or(
+                            Diagnostic::error(Code::InvalidValue, format!("Unknown binning rule '{}'", bins_value.value))
+                                .at(&bins_value)
+                                .with_note(format!("Expected one of the following: {}", Binning::NAMES.join(", ")))
+                        )?,
+                        _ => match bins_value.value.parse::<usize>() {
+                            Ok(count) if count > 0 && count as f64 <= histogram::MAX_BINS => Binning::Count(count),
+                            _ => {
+                                return Err(
+                                    Diagnostic::error(Code::InvalidValue, format!("Invalid number of bins {}", bins_value.value))
+                                        .at(&bins_value)
+                                        .with_note(format!("A histogram has between 1 and {} bins", histogram::MAX_BINS))
+                                );
+                            },
+                        },
+                    };
+                    binning = Some((bins, token));
+                },
+                "width" => {
+                    let width_value = self.get_number(&token)?;
+                    if width_value <= 0.0 {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Width value must be greater than 0")
+                                .at(&token)
+                        );
+                    }
+                    binning = Some((Binning::Width(width_value), token));
+                },
+                "density" => density = Some(self.get_bool(&token)?),
+                "name" => name = Some(self.get_string(&token)?),
+                "color" => color = Some(self.get_hex(&token)?),
+                "alpha" => {
+                    let alpha_value = self.get_number(&token)?;
+                    if !(0.0..=1.0).contains(&alpha_value) {
+                        return Err(
+                            Diagnostic::error(Code::InvalidValue, "Alpha value must be between 0 and 1")
+                                .at(&token)
+                        );
+                    }
+                    alpha = Some(alpha_value);
+                },
+                "clip" => clip = Some(self.get_bool(&token)?),
+                _ => {
+                    return Err(self.unexpected_keyword(&token, start, &["values", "data", "column", "header", "bins", "width", "density", "name", "color", "alpha", "clip"]));
+                },
+            }
+
+            self.consume(1);
+        }
+
+        let column = column.unwrap_or(Column::Index(1));
+        let rows = self.load_data(data.as_ref(), &[column], header, data_option.as_ref())?;
+        values.extend(rows.iter().map(|row| row[0]));
+        // empty cells, written nan, are not counted
+        values.retain(|value| value.is_finite());
+        if values.is_empty() {
+            return Err(
+                Diagnostic::error(Code::MissingKeyword, "Missing 'values' keyword")
+                    .at(start)
+                    .with_note("Need to specify the values of the histogram, or a 'data' file")
+            );
+        }
+
+        let bins = histogram::bin(&values, binning.as_ref().map_or(Binning::Sturges, |(binning, _)| *binning)).map_err(|e| {
+            Diagnostic::error(Code::InvalidValue, "Too many bins in the histogram")
+                .at(binning.as_ref().map_or(start, |(_, token)| token))
+                .with_note(e)
+                .with_help("Use a larger 'width' or set the number of 'bins'")
+        })?;
+        func.args.push(Arg::Points(bins.bars(density == Some(true))));
+        func.args.push(Arg::Width(bins.width));
+
+        if let Some(name) = name {
+            func.args.push(Arg::Name(name));
+        }
+
+        if let Some(color) = color {
+            func.args.push(Arg::Color(color));
+        }
+
+        if let Some(alpha) = alpha {
+            func.args.push(Arg::Alpha(alpha));
+        }
+
+        if let Some(clip) = clip {
+            func.args.push(Arg::Clip(clip));
+        }
+
+        self.functions.push(func);
+        Ok(())
+    }
+
+    /// Places the grouped bars side by side and the stacked ones on top of
+    /// each other, once every bars function is known
+    fn layout_bars(&mut self) {
+        let grouped = self.functions.iter()
+            .filter(|func| func.name == "bars" && collect_args(func).mode.as_deref() != Some("stacked"))
+            .count();
+        let mut slot = 0;
+        // the tops of the stacks above and below 0, at each position
+        let mut stacks: Vec<(f64, f64, f64)> = Vec::new();
+
+        for func in self.functions.iter_mut().filter(|func| func.name == "bars") {
+            let datas = collect_args(func);
+            if datas.mode.as_deref() != Some("stacked") {
+                func.args.push(Arg::Slot((slot, grouped)));
+                slot += 1;
+                continue;
+            }
+
+            let mut bases: Vec<f64> = Vec::new();
+            for (x, height) in datas.points.unwrap() {
+                let index = match stacks.iter().position(|stack| stack.0 == x) {
+                    Some(index) => index,
+                    None => {
+                        stacks.push((x, 0.0, 0.0));
+                        stacks.len() - 1
+                    },
+                };
+                let stack = &mut stacks[index];
+                if height < 0.0 {
+                    bases.push(stack.2);
+                    stack.2 += height;
+                } else {
+                    bases.push(stack.1);
+                    stack.1 += height;
+                }
+            }
+            func.args.push(Arg::Bases(bases));
+        }
+    }
+
+    /// Lexes and expands the source, keeping the tokens of both passes
+    /// whatever their errors, which are returned
+    fn preprocess(&mut self) -> Vec<Diagnostic> {
+        let (tokens, mut errors) = self.lexer.tokenize();
+        let (tokens, expansion_errors) = Expander::new(tokens).expand();
+        errors.extend(expansion_errors);
+        self.tokens = tokens;
+        errors
+    }
+
+    /// Byte offsets of the block around `offset`, from the declaration,
+    /// definition, function or `#end` before it to the one after it, a
+    /// closing `#end` being part of the block
+    fn block_around(&self, offset: usize) -> (usize, usize) {
+        let boundaries = self.tokens.iter()
+            .filter(|token| [TokenType::DECLARATION, TokenType::FUNCTION, TokenType::DEFINE].contains(&token.token_type));
+        let start = boundaries.clone().map(|token| token.span.start).filter(|start| *start <= offset).max().unwrap_or(0);
+        let end = match boundaries.filter(|token| token.span.start > offset).min_by_key(|token| token.span.start) {
+            Some(token) if token.value == "end" => token.span.end,
+            Some(token) => token.span.start,
+            None => usize::MAX,
+        };
+        (start, end)
+    }
+
+    /// Processes every declaration, definition and function, going on
+    /// after an error so that all of them are reported at once
+    fn process(&mut self) -> Result<(), Vec<Diagnostic>> {
+        let preprocess_errors = self.preprocess();
+        let mut errors = self.check_root();
+        let mut current_token = self.next();
+        while current_token.is_some() {
+            let token = current_token.unwrap();
+            let processed = match token.token_type {
+                TokenType::STRING   |
+                TokenType::INTERGER |
+                TokenType::FLOAT    |
+                TokenType::HEX      |
+                TokenType::SYMBOL   |
+                TokenType::KEYWORD  |
+                TokenType::VARNAME  |
+                TokenType::VAR      |
+                TokenType::COMPTIME |
+                TokenType::COMPVAR  |
+                TokenType::FSTRING  |
+                TokenType::BOOLEAN  |
+                TokenType::IDENTIFIER => {
+                    Err(
+                        Diagnostic::error(Code::UnexpectedToken, format!("Unexpected token '{}'", token.value))
+                            .at(&token)
+                            .with_note("Only declarations, definitions and functions are allowed at the top level")
+                    )
+                },
+
+                TokenType::DEFINE => {
+                    self.process_define()
+                },
+                TokenType::DECLARATION => {
+                    self.process_declaration()
+                },
+                TokenType::FUNCTION => {
+                    self.process_function()
+                },
+            };
+
+            if let Err(e) = processed {
+                errors.push(e);
+                self.synchronize();
+            }
+
+            self.consume(1);
+            current_token = self.next();
+        }
+
+        errors.extend(self.fit_axes());
+        errors.extend(self.check_grid());
+
+        // a block with a malformed word or a faulty compile-time directive
+        // is reported once, not for the values it then lacks
+        let faulty: Vec<(usize, usize)> = preprocess_errors.iter()
+            .filter_map(|e| e.location)
+            .map(|location| self.block_around(location.span.start))
+            .collect();
+        errors.retain(|e| !e.location.is_some_and(|location| {
+            faulty.iter().any(|(start, end)| (*start..*end).contains(&location.span.start))
+        }));
+        errors.extend(preprocess_errors);
+        if !errors.is_empty() {
+            errors.sort_by_key(|e| e.location.map(|location| location.span.start));
+            return Err(errors);
+        }
+        Ok(())
+    }
+
+    /// Compiles the program into an svg string
+    pub fn compile(&mut self) -> Result<String, Vec<Diagnostic>> {
+        self.process()?;
+        self.layout_bars();
+        self.gen_svg()
+    }
+
+
+    //// Functions for the generation of the SVG string
+    fn gen_svg(&mut self) -> Result<String, Vec<Diagnostic>> {
+        let root = self.root.as_ref().unwrap();
+        let entries = legend_entries(&self.functions);
+        let legend = self.legend.as_ref().filter(|_| !entries.is_empty());
+        let transform = Transform::new(root, legend.map(|legend| {
+            (legend.position, legend::size(&entries, legend.fontsize.unwrap_or(legend::FONT_SIZE)))
+        }));
+
+        let mut svg = String::new();
+        svg.push_str(
+            &format!(
+                "<svg viewBox=\"{} {} {} {}\" xmlns=\"http://www.w3.org/2000/svg\">\n",
+                transform.view.x,
+                transform.view.y,
+                transform.view.width,
+                transform.view.height,
+            )
+        );
+
+        svg.push_str(
+            &format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#{}\"/>\n",
+                transform.view.x,
+                transform.view.y,
+                transform.view.width,
+                transform.view.height,
+                root.background
+            )
+        );
+
+        if let Some(title) = &root.title {
+            svg.push_str(&format!("<title>{}</title>\n", escape(title)));
+        }
+        svg.push_str(gen_titles(root, &transform).as_str());
+
+        let mut errors: Vec<Diagnostic> = Vec::new();
+        if let Some(grid) = &self.grid {
+            svg.push_str(gen_grid(grid, root, &transform).as_str());
+        }
+
+        // root axis
+        svg.push_str(draw_axis(&transform, root).as_str());
+
+        svg.push_str(gen_clip_path(&transform).as_str());
+
+        // consecutive clipped elements share a group, so the order in
+        // which the elements are drawn is kept
+        let mut is_clipping = false;
+        for function in self.functions.iter() {
+            let clip = collect_args(function).clip.unwrap_or(true);
+            if clip != is_clipping {
+                svg.push_str(if clip { "<g clip-path=\"url(#plot)\">\n" } else { "</g>\n" });
+                is_clipping = clip;
+            }
+
+            let func = gen_function(function, &transform);
+            match func {
+                Ok(func) => svg.push_str(func.as_str()),
+                Err(e) => errors.push(e),
+            }
+        }
+        if is_clipping {
+            svg.push_str("</g>\n");
+        }
+
+        if let Some(legend) = legend {
+            svg.push_str(gen_legend(legend, &entries, root, &transform).as_str());
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
         svg.push_str("</svg>");
-        svg
+        Ok(svg)
 
     }
 
 }
 
+fn parse_number(token: &Token) -> Result<f64, Diagnostic> {
+    token.value.parse::<f64>().map_err(|_| {
+        Diagnostic::error(Code::InvalidLiteral, format!("Invalid number '{}'", token.value))
+            .at(token)
+    })
+}
 
-fn draw_axis(
-    x: Declaration,
-    y: Declaration,
-    w: f64,
-    h: f64,
-    color: String,
-) -> String {
-    let mut axis_string = String::new();
-    
-    let mut _x_min = 0.0;
-    let x_max = x.max;
-    let mut _y_min = 0.0;
-    let y_max = y.max;
+/// Checks that the range of an axis is not empty and doesn't have too many
+/// ticks, `step` being the keyword setting its step
+fn check_range(axis: &Declaration, define: &Token, step: Option<&Token>) -> Result<(), Diagnostic> {
+    let min = axis.min.unwrap_or(0.0);
+    if min >= axis.max {
+        return Err(
+            Diagnostic::error(Code::InvalidValue, format!("Empty range for the variable '{}'", axis.varname))
+                .at(define)
+                .with_note(format!("'min' is {} and 'max' is {}", min, axis.max))
+                .with_help("'min' must be lower than 'max'")
+        );
+    }
 
-    if x.min.is_some() {
-        _x_min = x.min.unwrap();
+    if axis::tick_count(axis) > axis::MAX_TICKS {
+        return Err(
+            Diagnostic::error(Code::InvalidValue, format!("Too many ticks for the variable '{}'", axis.varname))
+                .at(step.unwrap_or(define))
+                .with_note(format!("An axis can't have more than {} ticks", axis::MAX_TICKS))
+                .with_help("Use a larger 'step' or fewer 'minor' ticks")
+        );
     }
-    if y.min.is_some() {
-        _y_min = y.min.unwrap();
+    Ok(())
+}
+
+fn parse_color(token: Token) -> Result<String, Diagnostic> {
+    if token.value.len() != 3 && token.value.len() != 6 {
+        return Err(
+            Diagnostic::error(Code::InvalidLiteral, format!("Invalid color '0x{}'", token.value))
+                .at(&token)
+                .with_note(format!("A color has 3 or 6 hexadecimal digits, found {}", token.value.len()))
+                .with_help("Write colors like 0xebdbb2 or 0xfff")
+        );
     }
+    Ok(token.value)
+}
 
-    // TODO: removing the min choice
-    let mut from = (0.0, 0.0);
-    let mut to = (x_max, 10.0);
+
+/// Draws the axes through the origin, or along the edge of the plot area
+/// when the origin is out of the range of the other axis, with their ticks,
+/// labels and titles
+fn draw_axis(transform: &Transform, root: &Root) -> String {
+    let mut axis_string = String::new();
+
+    let (x_axis, y_axis) = &root.axis;
+    let (x_min, x_max) = transform.x;
+    let (y_min, y_max) = transform.y;
+    let (x_origin, y_origin) = transform.origin();
+    let color = root.color.clone();
     let thickness = 1.0;
-    let color = color.clone();
 
     let mut func = Function {
-        name: String::from("line"), 
+        name: String::from("line"),
+        args: vec![
+            Arg::From((x_min, y_origin)),
+            Arg::To((x_max, y_origin)),
+            Arg::Color(color.clone()),
+            Arg::Thickness(thickness)
+        ]
+    };
+
+    axis_string.push_str(gen_line(&func, transform).as_str());
+
+    func = Function {
+        name: String::from("line"),
         args: vec![
-            Arg::From(from), 
-            Arg::To(to), 
-            Arg::Color(color.clone()), 
+            Arg::From((x_origin, y_min)),
+            Arg::To((x_origin, y_max)),
+            Arg::Color(color.clone()),
             Arg::Thickness(thickness)
         ]
     };
 
-    axis_string.push_str(gen_line(&func, w, h).as_str());
+    axis_string.push_str(gen_line(&func, transform).as_str());
+
+    // x ticks go down from the axis and y ticks go left of it
+    let (axis_x, axis_y) = transform.point((x_origin, y_origin));
+    let tick_length = |tick: &axis::Tick| if tick.major { axis::TICK_LENGTH } else { axis::TICK_LENGTH / 2.0 };
+    let mut ticks = String::new();
+    for tick in axis::ticks(x_axis) {
+        ticks.push_str(&format!("M{} {} v{} ", format_coordinate(transform.x(tick.value)), format_coordinate(axis_y), tick_length(&tick)));
+    }
+    for tick in axis::ticks(y_axis) {
+        ticks.push_str(&format!("M{} {} h{} ", format_coordinate(axis_x), format_coordinate(transform.y(tick.value)), -tick_length(&tick)));
+    }
+    axis_string.push_str(&format!("<path d=\"{}\" stroke=\"#{}\" stroke-width=\"{}\" fill=\"none\"/>\n", ticks.trim_end(), color, thickness));
+
+    // the labels where the axes cross would lie on the other axis, unless
+    // they cross at the corner and their labels go opposite ways
+    let is_corner = x_origin == x_min && y_origin == y_min;
+    let x_font = axis::font_size(x_axis);
+    axis_string.push_str(&format!("<g font-family=\"sans-serif\" font-size=\"{}\" fill=\"#{}\" text-anchor=\"middle\">\n", x_font, color));
+    for (value, label) in axis::labels(x_axis) {
+        if value == x_origin && !is_corner {
+            continue;
+        }
+        axis_string.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\">{}</text>\n",
+            format_coordinate(transform.x(value)),
+            format_coordinate(axis_y + axis::TICK_LENGTH + axis::LABEL_GAP + x_font * 0.8),
+            escape(&label)
+        ));
+    }
+    axis_string.push_str("</g>\n");
+
+    let y_font = axis::font_size(y_axis);
+    axis_string.push_str(&format!("<g font-family=\"sans-serif\" font-size=\"{}\" fill=\"#{}\" text-anchor=\"end\">\n", y_font, color));
+    for (value, label) in axis::labels(y_axis) {
+        if value == y_origin && !is_corner {
+            continue;
+        }
+        axis_string.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" dy=\"0.35em\">{}</text>\n",
+            format_coordinate(axis_x - axis::TICK_LENGTH - axis::LABEL_GAP),
+            format_coordinate(transform.y(value)),
+            escape(&label)
+        ));
+    }
+    axis_string.push_str("</g>\n");
+
+    // the titles are centered on the plot area, at the outer edge of the
+    // margins
+    let area = transform.area;
+    let view = transform.view;
+    // a legend below the graph is in the bottom margin, under the x title
+    let bottom = match transform.legend {
+        Some(legend) if legend.y > area.y + area.height => legend.y,
+        _ => view.y + view.height,
+    };
+    if let Some(name) = &x_axis.name {
+        axis_string.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{}\" fill=\"#{}\" text-anchor=\"middle\">{}</text>\n",
+            format_coordinate(area.x + area.width / 2.0),
+            format_coordinate(bottom - PADDING),
+            axis::title_size(x_axis),
+            color,
+            escape(name)
+        ));
+    }
+    if let Some(name) = &y_axis.name {
+        let (x, y) = (view.x + PADDING + axis::title_size(y_axis), area.y + area.height / 2.0);
+        axis_string.push_str(&format!(
+            "<text x=\"{x}\" y=\"{y}\" transform=\"rotate(-90 {x} {y})\" font-family=\"sans-serif\" font-size=\"{}\" fill=\"#{}\" text-anchor=\"middle\">{}</text>\n",
+            axis::title_size(y_axis),
+            color,
+            escape(name),
+            x = format_coordinate(x),
+            y = format_coordinate(y),
+        ));
+    }
+
+    axis_string
+}
+
+/// The title and subtitle of the graph, centered over the plot area
+fn gen_titles(root: &Root, transform: &Transform) -> String {
+    let mut titles_string = String::new();
+    let x = format_coordinate(transform.area.x + transform.area.width / 2.0);
+    let mut y = transform.view.y + PADDING;
+
+    if let Some(title) = &root.title {
+        y += TITLE_SIZE;
+        titles_string.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{}\" font-weight=\"bold\" fill=\"#{}\" text-anchor=\"middle\">{}</text>\n",
+            x,
+            format_coordinate(y),
+            TITLE_SIZE,
+            root.color,
+            escape(title)
+        ));
+        y += axis::LABEL_GAP;
+    }
+    if let Some(subtitle) = &root.subtitle {
+        y += SUBTITLE_SIZE;
+        titles_string.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{}\" fill=\"#{}\" fill-opacity=\"0.8\" text-anchor=\"middle\">{}</text>\n",
+            x,
+            format_coordinate(y),
+            SUBTITLE_SIZE,
+            root.color,
+            escape(subtitle)
+        ));
+    }
+
+    titles_string
+}
+
+/// The named elements, in the order they are drawn
+fn legend_entries(functions: &[Function]) -> Vec<Entry> {
+    functions.iter().filter_map(|function| {
+        let datas = collect_args(function);
+        let color = datas.color.clone().unwrap_or(String::from("000000"));
+        let swatch = match function.name.as_str() {
+            "line" | "graph" | "polyline" => Swatch::Line {
+                color,
+                thickness: datas.thickness.unwrap_or(1.0),
+                dash: datas.dash,
+            },
+            "point" => Swatch::Point { color },
+            "scatter" => Swatch::Marker {
+                marker: datas.marker.unwrap_or(String::from("circle")),
+                color,
+            },
+            "bars" | "histogram" => Swatch::Area {
+                color: None,
+                fill: Some(bars_color(&datas)),
+                alpha: datas.alpha.unwrap_or(1.0),
+            },
+            "rect" | "circle" | "polygon" => Swatch::Area {
+                color: datas.color.or(datas.fill.is_none().then(|| String::from("000000"))),
+                fill: datas.fill,
+                alpha: datas.alpha.unwrap_or(1.0),
+            },
+            _ => return None,
+        };
+        datas.name.map(|name| Entry { name, swatch })
+    }).collect()
+}
 
-    from = (0.0, 0.0);
-    to = (10.0, y_max);
-    func = Function {
-        name: String::from("line"), 
-        args: vec![
-            Arg::From(from), 
-            Arg::To(to), 
-            Arg::Color(color), 
-            Arg::Thickness(thickness)
-        ]
-    };
+/// A box listing the entries, each with a sample of how its element is drawn
+fn gen_legend(legend: &Legend, entries: &[Entry], root: &Root, transform: &Transform) -> String {
+    let mut legend_string = String::new();
+    let area = transform.legend.unwrap();
+    let color = legend.color.as_ref().unwrap_or(&root.color);
+    let background = legend.background.as_ref().unwrap_or(&root.background);
+    let font_size = legend.fontsize.unwrap_or(legend::FONT_SIZE);
+    let row_height = legend::row_height(font_size);
+
+    legend_string.push_str(&format!("<g font-family=\"sans-serif\" font-size=\"{}\" fill=\"#{}\">\n", font_size, color));
+    legend_string.push_str(&format!(
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#{}\" fill-opacity=\"{}\" stroke=\"#{}\" stroke-opacity=\"0.5\" stroke-width=\"0.5\"/>\n",
+        format_coordinate(area.x),
+        format_coordinate(area.y),
+        format_coordinate(area.width),
+        format_coordinate(area.height),
+        background,
+        legend.alpha.unwrap_or(0.8),
+        color
+    ));
+
+    let x = area.x + legend::PADDING;
+    for (i, entry) in entries.iter().enumerate() {
+        let center = area.y + legend::PADDING + (i as f64 + 0.5) * row_height;
+        let y = format_coordinate(center);
+        match &entry.swatch {
+            Swatch::Line { color, thickness, dash } => legend_string.push_str(&format!(
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#{}\" stroke-width=\"{}\" stroke-linecap=\"round\"{}/>\n",
+                format_coordinate(x),
+                y,
+                format_coordinate(x + legend::SWATCH_WIDTH),
+                y,
+                color,
+                thickness,
+                dash.as_ref().map_or(String::new(), |dash| format!(" stroke-dasharray=\"{}\"", dash))
+            )),
+            Swatch::Point { color } => legend_string.push_str(&format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"2\" fill=\"#{}\"/>\n",
+                format_coordinate(x + legend::SWATCH_WIDTH / 2.0),
+                y,
+                color
+            )),
+            Swatch::Marker { marker, color } => {
+                let path = marker_path(marker, (x + legend::SWATCH_WIDTH / 2.0, center), SCATTER_SIZE);
+                let style = match ["cross", "plus"].contains(&marker.as_str()) {
+                    true => format!("stroke=\"#{}\" fill=\"none\"", color),
+                    false => format!("fill=\"#{}\"", color),
+                };
+                legend_string.push_str(&format!("<path d=\"{}\" {}/>\n", path, style));
+            },
+            Swatch::Area { color, fill, alpha } => legend_string.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" stroke=\"{}\" fill=\"{}\" fill-opacity=\"{}\"/>\n",
+                format_coordinate(x),
+                format_coordinate(area.y + legend::PADDING + (i as f64 + 0.25) * row_height),
+                format_coordinate(legend::SWATCH_WIDTH),
+                format_coordinate(row_height / 2.0),
+                color.as_ref().map_or(String::from("none"), |color| format!("#{}", color)),
+                fill.as_ref().map_or(String::from("none"), |fill| format!("#{}", fill)),
+                alpha
+            )),
+        }
+        legend_string.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" dy=\"0.35em\">{}</text>\n",
+            format_coordinate(x + legend::SWATCH_WIDTH + legend::PADDING),
+            y,
+            escape(&entry.name)
+        ));
+    }
+    legend_string.push_str("</g>\n");
 
-    axis_string.push_str(gen_line(&func, w, h).as_str());
+    legend_string
+}
 
-    axis_string
+/// The axis whose ticks are the lines of the grid along it
+fn grid_axis(grid: &Grid, axis: &Declaration, step: Option<f64>) -> Declaration {
+    Declaration {
+        step: step.or(axis.step),
+        minor: grid.minor,
+        ..axis.clone()
+    }
 }
 
-fn gen_grid(grid: Grid, w: f64, h: f64) -> String {
+/// Draws the grid lines at the multiples of the steps of the grid, or of
+/// the axes, with `minor` thinner and fainter lines between them
+fn gen_grid(grid: &Grid, root: &Root, transform: &Transform) -> String {
     let mut grid_string = String::new();
-    let mut alpha = 0.5;
-    if grid.alpha.is_some() {
-        alpha = grid.alpha.unwrap();
-    }
-    
-    let mut thickness = 1.0;
-    if grid.thickness.is_some() {
-        thickness = grid.thickness.unwrap();
-    }
-
-    let mut step = 1.0;
-    if grid.step.is_some() {
-        step = grid.step.unwrap();
-    }
-
-    /*
-    https://stackoverflow.com/questions/14208673/how-to-draw-grid-using-html5-and-canvas-or-svg
-    Need to generate a pattern like this:
-    <defs>
-      <pattern id="grid" width="80" height="80" patternUnits="userSpaceOnUse">
-        <path d="M 80 0 L 0 0 0 80" fill="none" stroke="gray" stroke-width="1"/>
-      </pattern>
-    </defs>
-    <rect width="100%" height="100%" fill="url(#grid)" />
-     */
-
-    grid_string.push_str("<defs>\n");
-    grid_string.push_str(&format!("<pattern id=\"grid\" width=\"{}\" height=\"{}\" patternUnits=\"userSpaceOnUse\">\n", step, step));
-    grid_string.push_str(&format!("<path d=\"M {} 0 L 0 0 0 {}\" fill=\"none\" stroke=\"#{}\" stroke-width=\"{}\" stroke-opacity=\"{}\"/>\n", step, step, grid.color, thickness, alpha));
-    grid_string.push_str("</pattern>\n");
-    grid_string.push_str("</defs>\n");
-    grid_string.push_str(&format!("<rect width=\"{}\" height=\"{}\" fill=\"url(#grid)\" />\n", w, h));
-    
+    let alpha = grid.alpha.unwrap_or(0.5);
+    let thickness = grid.thickness.unwrap_or(1.0);
+    let area = transform.area;
+    let (x_axis, y_axis) = &root.axis;
+
+    let mut major = String::new();
+    let mut minor = String::new();
+    for tick in axis::ticks(&grid_axis(grid, x_axis, grid.xstep)) {
+        let path = if tick.major { &mut major } else { &mut minor };
+        path.push_str(&format!("M{} {} v{} ", format_coordinate(transform.x(tick.value)), format_coordinate(area.y), format_coordinate(area.height)));
+    }
+    for tick in axis::ticks(&grid_axis(grid, y_axis, grid.ystep)) {
+        let path = if tick.major { &mut major } else { &mut minor };
+        path.push_str(&format!("M{} {} h{} ", format_coordinate(area.x), format_coordinate(transform.y(tick.value)), format_coordinate(area.width)));
+    }
+
+    let dash = grid.dash.as_ref().map_or(String::new(), |dash| format!(" stroke-dasharray=\"{}\"", dash));
+    for (path, thickness, alpha) in [(minor, thickness / 2.0, alpha / 2.0), (major, thickness, alpha)] {
+        if !path.is_empty() {
+            grid_string.push_str(&format!("<path d=\"{}\" fill=\"none\" stroke=\"#{}\" stroke-width=\"{}\" stroke-opacity=\"{}\"{}/>\n", path.trim_end(), grid.color, thickness, alpha, dash));
+        }
+    }
+
     grid_string
+}
 
+/// The plot area, outside of which the elements are hidden unless they
+/// are given `clip false`
+fn gen_clip_path(transform: &Transform) -> String {
+    let area = transform.area;
+    let mut clip_string = String::new();
+    clip_string.push_str("<defs>\n");
+    clip_string.push_str("<clipPath id=\"plot\">\n");
+    clip_string.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" />\n", area.x, area.y, area.width, area.height));
+    clip_string.push_str("</clipPath>\n");
+    clip_string.push_str("</defs>\n");
+    clip_string
 }
 
-fn gen_function(func: Function, w: f64, h: f64) -> String {
+fn gen_function(func: &Function, transform: &Transform) -> Result<String, Diagnostic> {
     match func.name.as_ref() {
         "line" => {
-            gen_line(&func, w, h)
+            Ok(gen_line(func, transform))
         },
         "graph" => {
-            gen_graph(&func, w, h)
+            gen_graph(func, transform)
         },
         "point" => {
-            gen_point(&func, w, h)
+            Ok(gen_point(func, transform))
+        },
+        "text" => {
+            Ok(gen_text(func, transform))
+        },
+        "rect" => {
+            Ok(gen_rect(func, transform))
+        },
+        "circle" => {
+            Ok(gen_circle(func, transform))
+        },
+        "polygon" => {
+            Ok(gen_polygon(func, transform))
+        },
+        "scatter" => {
+            Ok(gen_scatter(func, transform))
+        },
+        "polyline" => {
+            Ok(gen_polyline(func, transform))
+        },
+        "bars" | "histogram" => {
+            Ok(gen_bars(func, transform))
         },
         _ => {
-            String::new()
+            Ok(String::new())
         },
-    }    
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -1212,7 +2805,28 @@ pub struct ArgData {
     pub name: Option<String>,
     pub color: Option<String>,
     pub thickness: Option<f64>,
-    pub func: Option<String>,
+    pub func: Option<Box<Expression>>,
+    pub samples: Option<usize>,
+    pub resolution: Option<f64>,
+    pub asymptotes: Option<bool>,
+    pub clip: Option<bool>,
+    pub content: Option<String>,
+    pub size: Option<f64>,
+    pub anchor: Option<String>,
+    pub rotate: Option<f64>,
+    pub pixel: Option<bool>,
+    pub fill: Option<String>,
+    pub alpha: Option<f64>,
+    pub radius: Option<(f64, f64)>,
+    pub points: Option<Vec<(f64, f64)>>,
+    pub marker: Option<String>,
+    pub dash: Option<String>,
+    pub curve: Option<String>,
+    pub mode: Option<String>,
+    pub width: Option<f64>,
+    pub colors: Option<Vec<String>>,
+    pub slot: Option<(usize, usize)>,
+    pub bases: Option<Vec<f64>>,
 }
 
 fn collect_args(func: &Function) -> ArgData {
@@ -1224,17 +2838,38 @@ fn collect_args(func: &Function) -> ArgData {
         color: None,
         thickness: None,
         func: None,
+        samples: None,
+        resolution: None,
+        asymptotes: None,
+        clip: None,
+        content: None,
+        size: None,
+        anchor: None,
+        rotate: None,
+        pixel: None,
+        fill: None,
+        alpha: None,
+        radius: None,
+        points: None,
+        marker: None,
+        dash: None,
+        curve: None,
+        mode: None,
+        width: None,
+        colors: None,
+        slot: None,
+        bases: None,
     };
     for argument in func.args.iter() {
         match argument {
             Arg::From(from) => {
-                data.from = Some(from.clone());
+                data.from = Some(*from);
             },
             Arg::To(to) => {
-                data.to = Some(to.clone());
+                data.to = Some(*to);
             },
             Arg::At(at) => {
-                data.at = Some(at.clone());
+                data.at = Some(*at);
             },
             Arg::Name(name) => {
                 data.name = Some(name.clone());
@@ -1243,150 +2878,728 @@ fn collect_args(func: &Function) -> ArgData {
                 data.color = Some(color.clone());
             },
             Arg::Thickness(thickness) => {
-                data.thickness = Some(thickness.clone());
+                data.thickness = Some(*thickness);
             },
             Arg::Func(func) => {
                 data.func = Some(func.clone());
             },
+            Arg::Samples(samples) => {
+                data.samples = Some(*samples);
+            },
+            Arg::Resolution(resolution) => {
+                data.resolution = Some(*resolution);
+            },
+            Arg::Asymptotes(asymptotes) => {
+                data.asymptotes = Some(*asymptotes);
+            },
+            Arg::Clip(clip) => {
+                data.clip = Some(*clip);
+            },
+            Arg::Content(content) => {
+                data.content = Some(content.clone());
+            },
+            Arg::Size(size) => {
+                data.size = Some(*size);
+            },
+            Arg::Anchor(anchor) => {
+                data.anchor = Some(anchor.clone());
+            },
+            Arg::Rotate(rotate) => {
+                data.rotate = Some(*rotate);
+            },
+            Arg::Pixel(pixel) => {
+                data.pixel = Some(*pixel);
+            },
+            Arg::Fill(fill) => {
+                data.fill = Some(fill.clone());
+            },
+            Arg::Alpha(alpha) => {
+                data.alpha = Some(*alpha);
+            },
+            Arg::Radius(radius) => {
+                data.radius = Some(*radius);
+            },
+            Arg::Points(points) => {
+                data.points = Some(points.clone());
+            },
+            Arg::Marker(marker) => {
+                data.marker = Some(marker.clone());
+            },
+            Arg::Dash(dash) => {
+                data.dash = Some(dash.clone());
+            },
+            Arg::Curve(curve) => {
+                data.curve = Some(curve.clone());
+            },
+            Arg::Mode(mode) => {
+                data.mode = Some(mode.clone());
+            },
+            Arg::Width(width) => {
+                data.width = Some(*width);
+            },
+            Arg::Colors(colors) => {
+                data.colors = Some(colors.clone());
+            },
+            Arg::Slot(slot) => {
+                data.slot = Some(*slot);
+            },
+            Arg::Bases(bases) => {
+                data.bases = Some(bases.clone());
+            },
         }
     }
     data
 }
 
-fn gen_line(func: &Function, _w: f64, h: f64) -> String {
+fn gen_line(func: &Function, transform: &Transform) -> String {
     let datas = collect_args(func);
-    let mut from = datas.from.unwrap();
-    let mut to = datas.to.unwrap();
+    let from = transform.point(datas.from.unwrap());
+    let to = transform.point(datas.to.unwrap());
     let name = datas.name;
     let color = datas.color;
     let thickness = datas.thickness;
 
+    // an end out of the domain of a log scale has no position
+    if ![from.0, from.1, to.0, to.1].iter().all(|value| value.is_finite()) {
+        return String::new();
+    }
+
     let mut line = String::new();
-    // in svg the axes are inverted so we need to invert the y axis
-    // and add 10 as padding
-    from = (from.0 + 10.0, h - from.1);
-    to = (to.0 + 10.0, h - to.1);
     line.push_str("<line ");
     line.push_str(&format!("x1=\"{}\" ", from.0));
     line.push_str(&format!("y1=\"{}\" ", from.1));
-    line.push_str(&format!("x2=\"{}\" ", to.0 - 10.0));
-    line.push_str(&format!("y2=\"{}\" ", to.1 + 10.0));
+    line.push_str(&format!("x2=\"{}\" ", to.0));
+    line.push_str(&format!("y2=\"{}\" ", to.1));
     line.push_str("stroke-linecap=\"round\" ");
-    if name.is_some() {
-        let string = name.unwrap();
-        line.push_str(&format!("name=\"{}\" ", string));
-    }
 
-    if color.is_some() {
-        let string = color.unwrap();
+    if let Some(string) = color {
         line.push_str(&format!("stroke=\"#{}\" ", string));
     }
 
-    if thickness.is_some() {
-        let thickness = thickness.unwrap();
+    if let Some(thickness) = thickness {
         line.push_str(&format!("stroke-width=\"{}\" ", thickness));
     }
 
-    line.push_str("/>\n");
+    line.push_str(&close_element("line", name));
     line
 }
 
-fn gen_point(func: &Function, _w: f64, h: f64) -> String {
+fn gen_point(func: &Function, transform: &Transform) -> String {
     let datas = collect_args(func);
-    let mut at = datas.at.unwrap();
+    let at = transform.point(datas.at.unwrap());
     let name = datas.name;
     let color = datas.color;
 
+    // out of the domain of a log scale
+    if !at.0.is_finite() || !at.1.is_finite() {
+        return String::new();
+    }
+
     let mut point = String::new();
 
-    // in svg the axes are inverted so we need to invert the y axis
-    // and add 10 as padding
-    at = (at.0 + 10.0, h - at.1);
     point.push_str("<circle ");
     point.push_str(&format!("cx=\"{}\" ", at.0));
     point.push_str(&format!("cy=\"{}\" ", at.1));
     point.push_str("r=\"2\" ");
-    if name.is_some() {
-        let string = name.unwrap();
-        point.push_str(&format!("name=\"{}\" ", string));
-    }
 
-    if color.is_some() {
-        let string = color.unwrap();
+    if let Some(string) = color {
         point.push_str(&format!("stroke=\"#{}\" ", string));
         point.push_str(&format!("fill=\"#{}\" ", string));
     } else {
         point.push_str("stroke=\"#000000\" ");
     }
 
-    point.push_str("/>\n");
+    point.push_str(&close_element("circle", name));
 
     point
 }
 
-fn gen_graph(func: &Function, w: f64, h: f64) -> String {
+fn gen_text(func: &Function, transform: &Transform) -> String {
+    let datas = collect_args(func);
+    let at = match datas.pixel {
+        Some(true) => datas.at.unwrap(),
+        _ => transform.point(datas.at.unwrap()),
+    };
+    let name = datas.name;
+    let color = datas.color;
+
+    // out of the domain of a log scale
+    if !at.0.is_finite() || !at.1.is_finite() {
+        return String::new();
+    }
+
+    let mut text = String::new();
+
+    text.push_str("<text ");
+    text.push_str(&format!("x=\"{}\" ", format_coordinate(at.0)));
+    text.push_str(&format!("y=\"{}\" ", format_coordinate(at.1)));
+    text.push_str("font-family=\"sans-serif\" ");
+    text.push_str(&format!("font-size=\"{}\" ", datas.size.unwrap_or(TEXT_SIZE)));
+
+    if let Some(string) = color {
+        text.push_str(&format!("fill=\"#{}\" ", string));
+    }
+
+    if let Some(anchor) = datas.anchor {
+        text.push_str(&format!("text-anchor=\"{}\" ", anchor));
+    }
+
+    // svg angles go clockwise, the y axis pointing down
+    if let Some(rotate) = datas.rotate {
+        text.push_str(&format!("transform=\"rotate({} {} {})\" ", -rotate, format_coordinate(at.0), format_coordinate(at.1)));
+    }
+
+    text.push('>');
+    if let Some(name) = name {
+        text.push_str(&format!("<title>{}</title>", escape(&name)));
+    }
+    text.push_str(&escape(&datas.content.unwrap()));
+    text.push_str("</text>\n");
+
+    text
+}
+
+/// The outline and the inside of a shape, outlined in black when it is
+/// neither given a color nor a fill
+fn shape_style(datas: &ArgData) -> String {
+    let mut style = String::new();
+
+    match (&datas.color, &datas.fill) {
+        (Some(color), _) => style.push_str(&format!("stroke=\"#{}\" ", color)),
+        (None, None) => style.push_str("stroke=\"#000000\" "),
+        (None, Some(_)) => (),
+    }
+
+    if let Some(thickness) = datas.thickness {
+        style.push_str(&format!("stroke-width=\"{}\" ", thickness));
+    }
+
+    if let Some(fill) = &datas.fill {
+        style.push_str(&format!("fill=\"#{}\" ", fill));
+        if let Some(alpha) = datas.alpha {
+            style.push_str(&format!("fill-opacity=\"{}\" ", alpha));
+        }
+    } else {
+        style.push_str("fill=\"none\" ");
+    }
+
+    style
+}
+
+fn gen_rect(func: &Function, transform: &Transform) -> String {
+    let datas = collect_args(func);
+    let from = transform.point(datas.from.unwrap());
+    let to = transform.point(datas.to.unwrap());
+
+    // a corner out of the domain of a log scale has no position
+    if ![from.0, from.1, to.0, to.1].iter().all(|value| value.is_finite()) {
+        return String::new();
+    }
+
+    let mut rect = String::new();
+    rect.push_str("<rect ");
+    rect.push_str(&format!("x=\"{}\" ", format_coordinate(from.0.min(to.0))));
+    rect.push_str(&format!("y=\"{}\" ", format_coordinate(from.1.min(to.1))));
+    rect.push_str(&format!("width=\"{}\" ", format_coordinate((to.0 - from.0).abs())));
+    rect.push_str(&format!("height=\"{}\" ", format_coordinate((to.1 - from.1).abs())));
+    rect.push_str(&shape_style(&datas));
+    rect.push_str(&close_element("rect", datas.name));
+    rect
+}
+
+/// An ellipse whose radii are distances along the axes, so a circle in
+/// data space stays round only when both axes have the same scale
+fn gen_circle(func: &Function, transform: &Transform) -> String {
+    let datas = collect_args(func);
+    let (x, y) = datas.at.unwrap();
+    let radius = datas.radius.unwrap();
+    let center = transform.point((x, y));
+    let rx = (transform.x(x + radius.0) - center.0).abs();
+    let ry = (transform.y(y + radius.1) - center.1).abs();
+
+    // out of the domain of a log scale
+    if ![center.0, center.1, rx, ry].iter().all(|value| value.is_finite()) {
+        return String::new();
+    }
+
+    let mut circle = String::new();
+    circle.push_str("<ellipse ");
+    circle.push_str(&format!("cx=\"{}\" ", format_coordinate(center.0)));
+    circle.push_str(&format!("cy=\"{}\" ", format_coordinate(center.1)));
+    circle.push_str(&format!("rx=\"{}\" ", format_coordinate(rx)));
+    circle.push_str(&format!("ry=\"{}\" ", format_coordinate(ry)));
+    circle.push_str(&shape_style(&datas));
+    circle.push_str(&close_element("ellipse", datas.name));
+    circle
+}
+
+fn gen_polygon(func: &Function, transform: &Transform) -> String {
+    let datas = collect_args(func);
+    let points: Vec<(f64, f64)> = datas.points.as_ref().unwrap().iter().map(|point| transform.point(*point)).collect();
+
+    // a corner out of the domain of a log scale has no position
+    if !points.iter().all(|point| point.0.is_finite() && point.1.is_finite()) {
+        return String::new();
+    }
+
+    let points: Vec<String> = points.iter().map(|point| format!("{},{}", format_coordinate(point.0), format_coordinate(point.1))).collect();
+    let mut polygon = String::new();
+    polygon.push_str("<polygon ");
+    polygon.push_str(&format!("points=\"{}\" ", points.join(" ")));
+    polygon.push_str(&shape_style(&datas));
+    polygon.push_str(&close_element("polygon", datas.name));
+    polygon
+}
+
+/// The subpath of a marker of width `size` centered on `(x, y)`
+fn marker_path(marker: &str, (x, y): (f64, f64), size: f64) -> String {
+    let r = size / 2.0;
+    let f = format_coordinate;
+    match marker {
+        "square" => format!("M{} {}h{}v{}h{}z", f(x - r), f(y - r), f(size), f(size), f(-size)),
+        "diamond" => format!("M{} {}l{} {}l{} {}l{} {}z", f(x), f(y - r), f(r), f(r), f(-r), f(r), f(-r), f(-r)),
+        "triangle" => format!("M{} {}l{} {}h{}z", f(x), f(y - r), f(r), f(size), f(-size)),
+        "cross" => format!("M{} {}l{} {}M{} {}l{} {}", f(x - r), f(y - r), f(size), f(size), f(x - r), f(y + r), f(size), f(-size)),
+        "plus" => format!("M{} {}h{}M{} {}v{}", f(x - r), f(y), f(size), f(x), f(y - r), f(size)),
+        // two half circles
+        _ => format!("M{} {}a{} {} 0 1 0 {} 0a{} {} 0 1 0 {} 0", f(x - r), f(y), f(r), f(r), f(size), f(r), f(r), f(-size)),
+    }
+}
+
+/// Every marker of the scatter in a single path, the markers made of
+/// lines being stroked and the others filled
+fn gen_scatter(func: &Function, transform: &Transform) -> String {
+    let datas = collect_args(func);
+    let marker = datas.marker.unwrap_or(String::from("circle"));
+    let size = datas.size.unwrap_or(SCATTER_SIZE);
+    let color = datas.color.unwrap_or(String::from("000000"));
+
+    // points out of the domain of a log scale are left out
+    let path: String = datas.points.unwrap().iter()
+        .map(|point| transform.point(*point))
+        .filter(|point| point.0.is_finite() && point.1.is_finite())
+        .map(|point| marker_path(&marker, point, size))
+        .collect();
+
+    let mut scatter = String::new();
+    scatter.push_str("<g ");
+    if ["cross", "plus"].contains(&marker.as_str()) {
+        scatter.push_str(&format!("stroke=\"#{}\" ", color));
+        scatter.push_str("fill=\"none\" ");
+        if let Some(alpha) = datas.alpha {
+            scatter.push_str(&format!("stroke-opacity=\"{}\" ", alpha));
+        }
+    } else {
+        scatter.push_str(&format!("fill=\"#{}\" ", color));
+        if let Some(alpha) = datas.alpha {
+            scatter.push_str(&format!("fill-opacity=\"{}\" ", alpha));
+        }
+    }
+    scatter.push('>');
+    if let Some(name) = datas.name {
+        scatter.push_str(&format!("<title>{}</title>", escape(&name)));
+    }
+    scatter.push_str(&format!("<path d=\"{}\"/>", path));
+    scatter.push_str("</g>\n");
+    scatter
+}
+
+/// The path through `points`, in svg units, joined as `curve` says
+fn curve_path(points: &[(f64, f64)], curve: &str) -> String {
+    let f = format_coordinate;
+    let mut path = format!("M{} {}", f(points[0].0), f(points[0].1));
+    match curve {
+        // the tangent at a point is parallel to the line between its neighbours
+        "catmull-rom" => {
+            for i in 0..points.len() - 1 {
+                let (previous, from, to) = (points[i.saturating_sub(1)], points[i], points[i + 1]);
+                let next = points[(i + 2).min(points.len() - 1)];
+                path.push_str(&format!(
+                    "C{} {} {} {} {} {}",
+                    f(from.0 + (to.0 - previous.0) / 6.0), f(from.1 + (to.1 - previous.1) / 6.0),
+                    f(to.0 - (next.0 - from.0) / 6.0), f(to.1 - (next.1 - from.1) / 6.0),
+                    f(to.0), f(to.1)
+                ));
+            }
+        },
+        "monotone" => {
+            let tangents = monotone_tangents(points);
+            for i in 0..points.len() - 1 {
+                let (from, to) = (points[i], points[i + 1]);
+                let dx = (to.0 - from.0) / 3.0;
+                path.push_str(&format!(
+                    "C{} {} {} {} {} {}",
+                    f(from.0 + dx), f(from.1 + dx * tangents[i]),
+                    f(to.0 - dx), f(to.1 - dx * tangents[i + 1]),
+                    f(to.0), f(to.1)
+                ));
+            }
+        },
+        _ => {
+            for pair in points.windows(2) {
+                let (from, to) = (pair[0], pair[1]);
+                match curve {
+                    "step" => path.push_str(&format!("H{}V{}H{}", f((from.0 + to.0) / 2.0), f(to.1), f(to.0))),
+                    "step-before" => path.push_str(&format!("V{}H{}", f(to.1), f(to.0))),
+                    "step-after" => path.push_str(&format!("H{}V{}", f(to.0), f(to.1))),
+                    _ => path.push_str(&format!("L{} {}", f(to.0), f(to.1))),
+                }
+            }
+        },
+    }
+    path
+}
+
+/// Slopes at each point of a cubic through the points that never goes
+/// beyond them between two of them (Steffen's method)
+fn monotone_tangents(points: &[(f64, f64)]) -> Vec<f64> {
+    let secants: Vec<f64> = points.windows(2).map(|pair| {
+        let slope = (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0);
+        if slope.is_finite() { slope } else { 0.0 }
+    }).collect();
+    if secants.len() == 1 {
+        return vec![secants[0]; 2];
+    }
+
+    let mut tangents = vec![0.0; points.len()];
+    for i in 1..points.len() - 1 {
+        let (h0, h1) = (points[i].0 - points[i - 1].0, points[i + 1].0 - points[i].0);
+        let (s0, s1) = (secants[i - 1], secants[i]);
+        // flat at the extrema, where the secants have opposite signs
+        if s0 * s1 <= 0.0 {
+            continue;
+        }
+        let middle = (s0 * h1 + s1 * h0) / (h0 + h1);
+        let tangent = s0.signum() * s0.abs().min(s1.abs()).min(middle.abs() / 2.0) * 2.0;
+        tangents[i] = if tangent.is_finite() { tangent } else { 0.0 };
+    }
+    let last = points.len() - 1;
+    tangents[0] = (3.0 * secants[0] - tangents[1]) / 2.0;
+    tangents[last] = (3.0 * secants[last - 1] - tangents[last - 1]) / 2.0;
+    tangents
+}
+
+/// The points of a polyline in a single path
+fn gen_polyline(func: &Function, transform: &Transform) -> String {
+    let datas = collect_args(func);
+    let curve = datas.curve.unwrap_or(String::from("linear"));
+    let points: Vec<(f64, f64)> = datas.points.unwrap().iter().map(|point| transform.point(*point)).collect();
+
+    // a point out of the domain of a log scale breaks the polyline
+    let path: String = points.split(|point| !point.0.is_finite() || !point.1.is_finite())
+        .filter(|run| run.len() > 1)
+        .map(|run| curve_path(run, &curve))
+        .collect();
+
+    let mut polyline = String::new();
+    polyline.push_str("<path ");
+    polyline.push_str(&format!("d=\"{}\" ", path));
+    polyline.push_str("stroke-linecap=\"round\" stroke-linejoin=\"round\" ");
+    polyline.push_str(&format!("stroke=\"#{}\" ", datas.color.unwrap_or(String::from("000000"))));
+    polyline.push_str("fill=\"none\" ");
+
+    if let Some(thickness) = datas.thickness {
+        polyline.push_str(&format!("stroke-width=\"{}\" ", thickness));
+    }
+
+    if let Some(dash) = datas.dash {
+        polyline.push_str(&format!("stroke-dasharray=\"{}\" ", dash));
+    }
+
+    polyline.push_str(&close_element("path", datas.name));
+    polyline
+}
+
+/// The bars as rectangles in a group, each in its slot of the width when
+/// they are grouped
+fn gen_bars(func: &Function, transform: &Transform) -> String {
+    let datas = collect_args(func);
+    let color = bars_color(&datas);
+    let points = datas.points.unwrap();
+    // the bars take most of the space between two positions
+    let mut positions: Vec<f64> = points.iter().map(|point| point.0).collect();
+    positions.sort_by(f64::total_cmp);
+    let spacing = positions.windows(2).map(|pair| pair[1] - pair[0]).filter(|gap| *gap > 0.0).reduce(f64::min);
+    let width = datas.width.unwrap_or(BARS_WIDTH * spacing.unwrap_or(1.0));
+    let (slot, count) = datas.slot.unwrap_or((0, 1));
+    let bar_width = width / count as f64;
+    let offset = -width / 2.0 + slot as f64 * bar_width;
+    // a log axis has no 0, the bars then start at its bottom
+    let bottom = transform.area.y + transform.area.height;
+
+    let mut bars = String::new();
+    bars.push_str(&format!("<g fill=\"#{}\" ", color));
+    if let Some(alpha) = datas.alpha {
+        bars.push_str(&format!("fill-opacity=\"{}\" ", alpha));
+    }
+    bars.push('>');
+    if let Some(name) = datas.name {
+        bars.push_str(&format!("<title>{}</title>", escape(&name)));
+    }
+    bars.push('\n');
+
+    for (i, (x, height)) in points.iter().enumerate() {
+        let base = datas.bases.as_ref().map_or(0.0, |bases| bases[i]);
+        let left = transform.x(x + offset);
+        let right = transform.x(x + offset + bar_width);
+        let from = Some(transform.y(base)).filter(|y| y.is_finite()).unwrap_or(bottom);
+        let to = transform.y(base + height);
+        if ![left, right, from, to].iter().all(|value| value.is_finite()) {
+            continue;
+        }
+
+        bars.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"",
+            format_coordinate(left.min(right)),
+            format_coordinate(from.min(to)),
+            format_coordinate((right - left).abs()),
+            format_coordinate((to - from).abs())
+        ));
+        if let Some(colors) = &datas.colors {
+            bars.push_str(&format!(" fill=\"#{}\"", colors[i % colors.len()]));
+        }
+        bars.push_str("/>\n");
+    }
+    bars.push_str("</g>\n");
+    bars
+}
+
+/// Color of the bars that don't have one of their own, the first of the
+/// colors of the bars when they are given
+fn bars_color(datas: &ArgData) -> String {
+    datas.color.clone()
+        .or(datas.colors.as_ref().map(|colors| colors[0].clone()))
+        .unwrap_or(String::from("000000"))
+}
+
+/// Width of the bars, or of a group of bars, relative to the space between
+/// two positions
+const BARS_WIDTH: f64 = 0.8;
+
+/// Shapes of the markers of a scatter
+const MARKERS: [&str; 6] = ["circle", "square", "diamond", "triangle", "cross", "plus"];
+/// Width of the markers of a scatter that doesn't set one
+const SCATTER_SIZE: f64 = 4.0;
+/// Ways the points of a polyline are joined
+const CURVES: [&str; 6] = ["linear", "catmull-rom", "monotone", "step", "step-before", "step-after"];
+
+/// Font size of a text that doesn't set one
+const TEXT_SIZE: f64 = 10.0;
+
+/// Number of evenly spaced samples of a graph before refinement
+const GRAPH_SAMPLES: usize = 32;
+/// Maximum distance, in svg units, between a graph and its path
+const GRAPH_RESOLUTION: f64 = 0.5;
+/// Number of times an interval between two samples may be halved
+const MAX_SUBDIVISIONS: u32 = 12;
+/// Distance, in svg units, below which a point of a graph is considered to
+/// be on the line between its neighbours
+const COLLINEAR_TOLERANCE: f64 = 0.01;
+/// How far, in plot heights, a graph may be drawn beyond the plot area,
+/// so values close to a pole stay reasonable svg numbers
+const GRAPH_OVERFLOW: f64 = 1.0;
+
+/// Ends an element, its name being given as a title, which viewers show
+/// as a tooltip
+fn close_element(tag: &str, name: Option<String>) -> String {
+    match name {
+        Some(name) => format!("><title>{}</title></{}>\n", escape(&name), tag),
+        None => String::from("/>\n"),
+    }
+}
+
+/// Escapes the characters of a text that would be read as markup
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Formats an svg coordinate, two decimals being enough for the paths
+/// to be drawn precisely while keeping them short
+fn format_coordinate(value: f64) -> String {
+    let rounded = (value * 100.0).round() / 100.0;
+    if rounded == 0.0 {
+        String::from("0")
+    } else {
+        format!("{}", rounded)
+    }
+}
+
+/// The points of a path without those lying on the segment between the
+/// points kept around them, which a straight line would draw the same
+fn drop_collinear(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    // distance of `point` to the segment from `a` to `b`
+    let distance = |point: (f64, f64), a: (f64, f64), b: (f64, f64)| {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let length = dx * dx + dy * dy;
+        let t = if length > 0.0 { (((point.0 - a.0) * dx + (point.1 - a.1) * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+        (point.0 - a.0 - t * dx).hypot(point.1 - a.1 - t * dy)
+    };
+
+    let mut kept: Vec<(f64, f64)> = points.iter().take(1).copied().collect();
+    let mut start = 0;
+    for i in 1..points.len().saturating_sub(1) {
+        let (from, to) = (points[start], points[i + 1]);
+        if !points[start + 1..=i].iter().all(|point| distance(*point, from, to) <= COLLINEAR_TOLERANCE) {
+            kept.push(points[i]);
+            start = i;
+        }
+    }
+    if points.len() > 1 {
+        kept.push(points[points.len() - 1]);
+    }
+    kept
+}
+
+/// Whether the values all lie beyond the same edge of the band a graph is
+/// drawn in, where it is clamped to a straight line
+fn is_overflowing(transform: &Transform, values: [f64; 3]) -> bool {
+    let area = transform.area;
+    let ys = values.map(|value| transform.y(value));
+    ys.iter().all(|y| *y < area.y - GRAPH_OVERFLOW * area.height)
+        || ys.iter().all(|y| *y > area.y + (1.0 + GRAPH_OVERFLOW) * area.height)
+}
+
+/// Adds the samples of `f` strictly between `a` and `b` to `samples`,
+/// halving the interval as long as the curve strays from the chord
+/// between its ends by more than `resolution`. An interval that can't be
+/// halved anymore holds a jump or a pole if one of its halves still
+/// carries nearly all of its change, which a continuous function doesn't
+/// do; it is marked by a NaN sample so the path is broken there.
+fn subdivide<F>(
+    f: &F,
+    transform: &Transform,
+    a: (f64, f64),
+    b: (f64, f64),
+    resolution: f64,
+    depth: u32,
+    samples: &mut Vec<(f64, f64)>,
+) -> Result<(), Diagnostic>
+where
+    F: Fn(f64) -> Result<f64, Diagnostic>,
+{
+    let x = transform.data_x((transform.x(a.0) + transform.x(b.0)) / 2.0);
+    let middle = (x, f(x)?);
+    let is_finite = [a.1, b.1, middle.1].iter().all(|y| y.is_finite());
+    let is_smooth = if is_finite {
+        let chord = (transform.y(a.1) + transform.y(b.1)) / 2.0;
+        (transform.y(middle.1) - chord).abs() <= resolution || is_overflowing(transform, [a.1, middle.1, b.1])
+    } else {
+        // edges of the domain are located as precisely as features
+        !a.1.is_finite() && !b.1.is_finite() && !middle.1.is_finite()
+    };
+    if is_smooth {
+        return Ok(());
+    }
+    if depth >= MAX_SUBDIVISIONS {
+        let change = (middle.1 - a.1).abs().max((b.1 - middle.1).abs());
+        if is_finite && change >= 0.9 * (b.1 - a.1).abs() {
+            samples.push((x, f64::NAN));
+        }
+        return Ok(());
+    }
+
+    subdivide(f, transform, a, middle, resolution, depth + 1, samples)?;
+    samples.push(middle);
+    subdivide(f, transform, middle, b, resolution, depth + 1, samples)
+}
+
+fn gen_graph(func: &Function, transform: &Transform) -> Result<String, Diagnostic> {
     let datas = collect_args(func);
     let func = datas.func.unwrap();
     let name = datas.name;
     let color = datas.color;
     let thickness = datas.thickness;
+    let samples_count = datas.samples.unwrap_or(GRAPH_SAMPLES);
+    let resolution = datas.resolution.unwrap_or(GRAPH_RESOLUTION);
 
     let mut graph = String::new();
+    // values out of the domain of a log scale are left undefined
+    let f = |x: f64| func.eval(x).map(|y| if transform.y(y).is_finite() { y } else { f64::NAN });
+
+    // samples evenly spaced in the plot area, refined where the curve bends
+    let area = transform.area;
+    let mut samples: Vec<(f64, f64)> = Vec::new();
+    let mut previous: Option<(f64, f64)> = None;
+    for i in 0..samples_count {
+        let x = transform.data_x(area.x + area.width * i as f64 / (samples_count - 1) as f64);
+        let sample = (x, f(x)?);
+        if let Some(previous) = previous {
+            subdivide(&f, transform, previous, sample, resolution, 0, &mut samples)?;
+        }
+        samples.push(sample);
+        previous = Some(sample);
+    }
 
-    // Will be used to generate the path
-    let mut path = String::new();
-    let func_string = func.clone();
-    if func_string.len() == 0 {
-        println!("[ERROR]: Missing function argument");
-        exit(1);
-    }
-    for x in 0..(w as i32) {
-        let x = x as f64;
-
-        let expr = exmex::parse::<f64>(&func_string.as_str());
-
-        match expr {
-            Ok(expr) => {
-                let y = expr.eval(&[(x)]);
-                match y {
-                    Ok(y) => {
-                        if y.is_nan() {
-                            continue;
-                        }
+    // a break closer than this to its finite neighbour was located by
+    // subdividing, so it's a pole if the neighbour is out of the y range
+    let precision = 2.0 * area.width / (samples_count - 1) as f64 / 2_f64.powi(MAX_SUBDIVISIONS as i32);
+    let (y_min, y_max) = transform.y;
+    let is_pole = |finite: (f64, f64), other: (f64, f64)| {
+        (transform.x(other.0) - transform.x(finite.0)).abs() <= precision && (finite.1 < y_min || finite.1 > y_max)
+    };
+    // svg x of the poles
+    let mut poles: Vec<f64> = Vec::new();
+    let mut add_pole = |a: f64, b: f64| {
+        let x = (transform.x(a) + transform.x(b)) / 2.0;
+        if poles.last().is_none_or(|last| (x - last).abs() > 2.0 * precision) {
+            poles.push(x);
+        }
+    };
 
-                        let y = h - y;
-                
-                        if x == 0.0 {
-                            path.push_str(&format!("M {} {} ", x + 10.0, y));
-                        } else {
-                            path.push_str(&format!("L {} {} ", x + 10.0, y));
-                        }
-                    },
-                    Err(e) => {
-                        println!("[ERROR]: Cannot parse function {} -> {}", func, e.msg());
-                            exit(1);
-                    },
-                    
+    // the svg points of the path, a new subpath starting after every
+    // non-finite sample
+    let mut subpaths: Vec<Vec<(f64, f64)>> = Vec::new();
+    let mut previous: Option<(f64, f64)> = None;
+    for (x, y) in samples {
+        let is_drawing = previous.is_some_and(|previous| previous.1.is_finite());
+        if !y.is_finite() {
+            if let Some(previous) = previous.filter(|_| is_drawing) {
+                if is_pole(previous, (x, y)) {
+                    add_pole(previous.0, x);
                 }
-            },
-            Err(e) => {
-                println!("[ERROR]: Cannot parse function {} -> {}", func, e.msg());
-                exit(1);
-            },
+            }
+            previous = Some((x, y));
+            continue;
+        }
+        if let Some(previous) = previous.filter(|_| !is_drawing) {
+            if is_pole((x, y), previous) {
+                add_pole(previous.0, x);
+            }
+        }
+        previous = Some((x, y));
+
+        let svg_x = transform.x(x);
+        let svg_y = transform.y(y).clamp(
+            area.y - GRAPH_OVERFLOW * area.height,
+            area.y + (1.0 + GRAPH_OVERFLOW) * area.height,
+        );
+        if !is_drawing {
+            subpaths.push(Vec::new());
+        }
+        let subpath = subpaths.last_mut().unwrap();
+        // the samples crowding around a pole are clamped to the same point
+        let is_repeated = subpath.last().is_some_and(|last: &(f64, f64)| {
+            format_coordinate(last.0) == format_coordinate(svg_x) && format_coordinate(last.1) == format_coordinate(svg_y)
+        });
+        if !is_repeated {
+            subpath.push((svg_x, svg_y));
+        }
+    }
+
+    let mut path = String::new();
+    for subpath in subpaths {
+        for (i, (x, y)) in drop_collinear(&subpath).into_iter().enumerate() {
+            let command = if i == 0 { "M" } else { "L" };
+            path.push_str(&format!("{} {} {} ", command, format_coordinate(x), format_coordinate(y)));
         }
     }
 
     graph.push_str("<path ");
     graph.push_str(&format!("d=\"{}\" ", path));
     graph.push_str("stroke-linecap=\"round\" ");
-    
-    if name.is_some() {
-        let string = name.unwrap();
-        graph.push_str(&format!("name=\"{}\" ", string));
-    }
 
-    if color.is_some() {
-        let string = color.unwrap();
+
+    if let Some(string) = &color {
         graph.push_str(&format!("stroke=\"#{}\" ", string));
     } else {
         graph.push_str("stroke=\"#000000\" ");
@@ -1394,13 +3607,289 @@ fn gen_graph(func: &Function, w: f64, h: f64) -> String {
 
     graph.push_str("fill=\"none\" ");
 
-    if thickness.is_some() {
-        let thickness = thickness.unwrap();
+    if let Some(thickness) = thickness {
         graph.push_str(&format!("stroke-width=\"{}\" ", thickness));
     }
 
-    graph.push_str("/>\n");
+    graph.push_str(&close_element("path", name));
 
-    graph
+    if datas.asymptotes == Some(true) {
+        let stroke = color.unwrap_or(String::from("000000"));
+        for pole in poles {
+            let x = format_coordinate(pole);
+            graph.push_str("<line ");
+            graph.push_str(&format!("x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" ", x, area.y, x, area.y + area.height));
+            graph.push_str(&format!("stroke=\"#{}\" stroke-opacity=\"0.5\" stroke-dasharray=\"4 4\" ", stroke));
+            graph.push_str("/>\n");
+        }
+    }
+
+    Ok(graph)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A program with a 0..10 x axis and a 0..10 y axis, `blocks` being
+    /// added before its root
+    fn process(blocks: &str) -> (Interpreter, Result<(), Vec<Diagnostic>>) {
+        let source = format!("{}\n#define x\n    max 10\n#end\n#define y\n    max 10\n#end\n#root\n    box 0, 0, 100, 100\n    axis x, y\n#end", blocks);
+        let mut interpreter = Interpreter::new(source);
+        let processed = interpreter.process();
+        (interpreter, processed)
+    }
+
+    #[test]
+    fn grid_steps_are_checked_against_the_axes() {
+        let (_, processed) = process("#grid\n    ystep 0.001\n#end");
+        let errors = processed.unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Too many grid lines along the variable 'y'");
+        assert_eq!(errors[0].location.unwrap().line, 2);
+
+        let (_, processed) = process("#grid\n    step 2\n    minor 999\n#end");
+        assert_eq!(processed.unwrap_err()[0].location.unwrap().line, 3);
+        assert!(process("#grid\n    step 2\n    minor 4\n#end").1.is_ok());
+    }
+
+    #[test]
+    fn bar_values_are_placed_after_each_other() {
+        let (interpreter, processed) = process("@bars\n    points (4, 2)\n    values 1, 2\n#end");
+        assert!(processed.is_ok());
+        let points = collect_args(&interpreter.functions[0]).points.unwrap();
+        assert_eq!(points, [(4.0, 2.0), (1.0, 1.0), (2.0, 2.0)]);
+    }
+
+    #[test]
+    fn axis_ranges_map_to_the_plot_area() {
+        let (interpreter, processed) = process("");
+        assert!(processed.is_ok());
+        let transform = Transform::new(interpreter.root.as_ref().unwrap(), None);
+        let area = transform.area;
+        assert_eq!(transform.point((0.0, 0.0)), (area.x, area.y + area.height));
+        assert_eq!(transform.point((10.0, 10.0)), (area.x + area.width, area.y));
+        assert_eq!(transform.point((5.0, 2.5)), (area.x + area.width / 2.0, area.y + area.height * 0.75));
+    }
+
+    #[test]
+    fn graph_expressions_are_checked_once() {
+        let (_, processed) = process("@graph\n    func \"x +* 2\"\n#end\n@graph\n    func \"x + y\"\n#end");
+        let errors = processed.unwrap_err();
+        assert_eq!(errors[0].code, Code::InvalidExpression);
+        assert_eq!(errors[0].notes, ["a binary operator cannot be on the right another operator"]);
+        assert_eq!(errors[1].code, Code::Undefined);
+
+        let (interpreter, _) = process("@graph\n    func \"2 * x\"\n#end");
+        let func = collect_args(&interpreter.functions[0]).func.unwrap();
+        assert_eq!(func.eval(3.0).unwrap(), 6.0);
+    }
+
+    /// The points of the path of the graph in `block`
+    fn graph_points(block: &str) -> Vec<String> {
+        let (interpreter, processed) = process(block);
+        assert!(processed.is_ok());
+        let transform = Transform::new(interpreter.root.as_ref().unwrap(), None);
+        let graph = gen_graph(&interpreter.functions[0], &transform).unwrap();
+        let path = graph.split('"').nth(1).unwrap();
+        path.split(['M', 'L']).map(str::trim).filter(|command| !command.is_empty()).map(String::from).collect()
+    }
+
+    #[test]
+    fn graph_poles_dont_repeat_clamped_points() {
+        let points = graph_points("@graph\n    func \"1/x\"\n#end");
+        assert!(points.windows(2).all(|pair| pair[0] != pair[1]), "{:?}", points);
+        assert!(points.len() < 80, "{} points", points.len());
+    }
+
+    #[test]
+    fn straight_graphs_are_drawn_with_their_ends() {
+        assert_eq!(graph_points("@graph\n    func \"2\"\n#end").len(), 2);
+        assert_eq!(graph_points("@graph\n    func \"x / 2 + 1\"\n    samples 200\n#end").len(), 2);
+    }
+
+    #[test]
+    fn graph_samples_and_resolution() {
+        let fine = graph_points("@graph\n    func \"x^2 / 10\"\n    samples 2\n    resolution 0.1\n#end");
+        let coarse = graph_points("@graph\n    func \"x^2 / 10\"\n    samples 2\n    resolution 5\n#end");
+        assert!(coarse.len() > 2 && coarse.len() < fine.len(), "{} and {} points", coarse.len(), fine.len());
+
+        for value in ["samples 1", "samples 2.5", "resolution 0", "resolution -1"] {
+            let (_, processed) = process(&format!("@graph\n    func \"x\"\n    {}\n#end", value));
+            let errors = processed.unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", value);
+            assert_eq!(errors[0].code, Code::InvalidValue);
+            assert_eq!(errors[0].location.unwrap().line, 3);
+        }
+    }
+
+    #[test]
+    fn polyline_curves() {
+        let points = [(0.0, 0.0), (2.0, 1.0), (4.0, 0.0)];
+        assert_eq!(curve_path(&points, "linear"), "M0 0L2 1L4 0");
+        assert_eq!(curve_path(&points, "step"), "M0 0H1V1H2H3V0H4");
+        assert_eq!(curve_path(&points, "step-before"), "M0 0V1H2V0H4");
+        assert_eq!(curve_path(&points, "step-after"), "M0 0H2V1H4V0");
+
+        // flat at the extremum, so the curve doesn't overshoot it
+        assert_eq!(monotone_tangents(&points)[1], 0.0);
+        let tangents = monotone_tangents(&[(0.0, 0.0), (1.0, 1.0), (2.0, 4.0)]);
+        assert!(tangents.iter().all(|tangent| *tangent >= 0.0));
+    }
+
+    /// The svg of the program made by `process` with `blocks`
+    fn compile(blocks: &str) -> String {
+        let (mut interpreter, processed) = process(blocks);
+        assert!(processed.is_ok());
+        interpreter.layout_bars();
+        interpreter.gen_svg().unwrap()
+    }
+
+    /// Whether the first line of `svg` containing `part` is in a clipped group
+    fn is_clipped(svg: &str, part: &str) -> bool {
+        let mut is_clipped = false;
+        for line in svg.lines() {
+            match line {
+                "<g clip-path=\"url(#plot)\">" => is_clipped = true,
+                "</g>" if is_clipped => is_clipped = false,
+                _ if line.contains(part) => return is_clipped,
+                _ => {}
+            }
+        }
+        panic!("no line contains {:?}", part);
+    }
+
+    #[test]
+    fn clipped_elements_are_grouped() {
+        let svg = compile("#legend\n#end\n@point\n    at 1, 1\n    name \"first\"\n#end\n@text\n    at 4, 12\n    content \"note\"\n    space \"pixel\"\n#end\n@line\n    from 0, 0\n    to 20, 20\n    clip false\n#end\n@point\n    at 2, 2\n#end");
+        assert!(svg.contains("<clipPath id=\"plot\">\n<rect x=\"25.6\" y=\"10\" width=\"100\" height=\"100\" />"));
+
+        let is_clipped = |part: &str| is_clipped(&svg, part);
+        assert!(is_clipped("<circle cx=\"35.6\""));
+        assert!(is_clipped("<circle cx=\"45.6\""));
+        assert!(!is_clipped("<text x=\"4\" y=\"12\""));
+        assert!(!is_clipped("<line x1=\"25.6\" y1=\"110\" x2=\"225.6\""));
+        // the legend
+        assert!(!is_clipped("dy=\"0.35em\">first</text>"));
+        assert_eq!(svg.matches("<g clip-path").count(), 2);
+    }
+
+    #[test]
+    fn legend_positions() {
+        let (interpreter, _) = process("");
+        let root = interpreter.root.as_ref().unwrap();
+        let size = (40.0, 20.0);
+        let plain = Transform::new(root, None);
+        let place = |position: Position| {
+            let transform = Transform::new(root, Some((position, size)));
+            (transform, transform.legend.unwrap())
+        };
+
+        let (transform, legend) = place(Position::BottomLeft);
+        let area = transform.area;
+        assert_eq!(legend.x, area.x + legend::MARGIN);
+        assert_eq!(legend.y + legend.height, area.y + area.height - legend::MARGIN);
+        assert_eq!(transform.view, plain.view);
+
+        // next to the plot area, which the graph grows around
+        let (transform, legend) = place(Position::Right);
+        let (area, view) = (transform.area, transform.view);
+        assert!(legend.x >= area.x + area.width);
+        assert_eq!(legend.x + legend.width, view.x + view.width - PADDING);
+        assert_eq!(view.width, plain.view.width + size.0 + PADDING);
+
+        let (transform, legend) = place(Position::Bottom);
+        let (area, view) = (transform.area, transform.view);
+        assert!(legend.y >= area.y + area.height);
+        assert_eq!(legend.x + legend.width / 2.0, area.x + area.width / 2.0);
+        assert_eq!(view.height, plain.view.height + size.1 + PADDING);
+    }
+
+    #[test]
+    fn names_are_written_as_titles() {
+        assert_eq!(close_element("circle", Some(String::from("a < b"))), "><title>a &lt; b</title></circle>\n");
+        assert_eq!(close_element("circle", None), "/>\n");
+
+        let svg = compile("@point\n    at 1, 1\n    name \"sine & co\"\n#end\n@point\n    at 2, 2\n#end");
+        assert!(svg.contains("<circle cx=\"35.6\" cy=\"100\" r=\"2\" stroke=\"#000000\" ><title>sine &amp; co</title></circle>"));
+        assert!(svg.contains("<circle cx=\"45.6\" cy=\"90\" r=\"2\" stroke=\"#000000\" />"));
+    }
+
+    #[test]
+    fn text_spaces_and_rotation() {
+        let (interpreter, processed) = process("@text\n    at 1, 1\n    content \"data\"\n    rotate 45\n#end\n@text\n    at 4, 12\n    content \"pixel\"\n    space \"pixel\"\n#end");
+        assert!(processed.is_ok());
+        let transform = Transform::new(interpreter.root.as_ref().unwrap(), None);
+        // counterclockwise around `at`, in the svg units
+        let data = gen_text(&interpreter.functions[0], &transform);
+        assert!(data.starts_with("<text x=\"35.6\" y=\"100\" "), "{}", data);
+        assert!(data.contains("transform=\"rotate(-45 35.6 100)\""), "{}", data);
+        let pixel = gen_text(&interpreter.functions[1], &transform);
+        assert!(pixel.starts_with("<text x=\"4\" y=\"12\" "), "{}", pixel);
+        assert!(!pixel.contains("transform"));
+
+        let svg = compile("@text\n    at 1, 1\n    content \"data\"\n#end\n@text\n    at 4, 12\n    content \"pixel\"\n    space \"pixel\"\n#end\n@text\n    at 4, 30\n    content \"clipped pixel\"\n    space \"pixel\"\n    clip true\n#end");
+        assert!(is_clipped(&svg, ">data</text>"));
+        assert!(!is_clipped(&svg, ">pixel</text>"));
+        assert!(is_clipped(&svg, ">clipped pixel</text>"));
+    }
+
+    #[test]
+    fn shapes_are_placed_in_data_space() {
+        let (interpreter, processed) = process("@rect\n    from 3, 4\n    to 1, 1\n#end\n@circle\n    at 5, 5\n    radius 2, 1\n#end\n@polygon\n    points (0, 0), (10, 0), (5, 10)\n    fill 0xff0000\n#end");
+        assert!(processed.is_ok());
+        let transform = Transform::new(interpreter.root.as_ref().unwrap(), None);
+        assert_eq!(gen_rect(&interpreter.functions[0], &transform), "<rect x=\"35.6\" y=\"70\" width=\"20\" height=\"30\" stroke=\"#000000\" fill=\"none\" />\n");
+        assert_eq!(gen_circle(&interpreter.functions[1], &transform), "<ellipse cx=\"75.6\" cy=\"60\" rx=\"20\" ry=\"10\" stroke=\"#000000\" fill=\"none\" />\n");
+        assert_eq!(gen_polygon(&interpreter.functions[2], &transform), "<polygon points=\"25.6,110 125.6,110 75.6,10\" fill=\"#ff0000\" />\n");
+    }
+
+    #[test]
+    fn shape_arguments_are_checked() {
+        let cases = [
+            ("@rect\n    from 1, 1\n#end", Code::MissingKeyword, "Missing 'to' keyword", 1),
+            ("@circle\n    radius 1\n#end", Code::MissingKeyword, "Missing 'at' keyword", 1),
+            ("@circle\n    at 1, 1\n    radius 0\n#end", Code::InvalidValue, "Radius value must be greater than 0", 3),
+            ("@polygon\n    name \"empty\"\n#end", Code::MissingKeyword, "Missing 'points' keyword", 1),
+            ("@polygon\n    points (0, 0), (1, 1)\n#end", Code::InvalidValue, "A polygon needs at least 3 points", 2),
+            ("@rect\n    from 1, 1\n    to 2, 2\n    alpha 2\n#end", Code::InvalidValue, "Alpha value must be between 0 and 1", 4),
+        ];
+        for (block, code, message, line) in cases {
+            let errors = process(block).1.unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", block);
+            assert_eq!(errors[0].code, code, "{}", block);
+            assert_eq!(errors[0].message, message);
+            assert_eq!(errors[0].location.unwrap().line, line, "{}", block);
+        }
+
+        let errors = process("@polygon\n    points (0, 0), (1, 1)\n#end").1.unwrap_err();
+        assert_eq!(errors[0].notes, ["Found 2 points"]);
+    }
+
+    #[test]
+    fn scatter_markers_share_a_path() {
+        for marker in MARKERS {
+            let (interpreter, processed) = process(&format!("@scatter\n    points (1, 1), (2, 2), (3, 3)\n    marker \"{}\"\n    size 2\n#end", marker));
+            assert!(processed.is_ok(), "{}", marker);
+            let transform = Transform::new(interpreter.root.as_ref().unwrap(), None);
+            let scatter = gen_scatter(&interpreter.functions[0], &transform);
+            assert_eq!(scatter.matches("<path").count(), 1, "{}", scatter);
+
+            let path = scatter.split('"').find(|part| part.starts_with('M')).unwrap();
+            let expected: String = [(35.6, 100.0), (45.6, 90.0), (55.6, 80.0)].iter().map(|point| marker_path(marker, *point, 2.0)).collect();
+            assert_eq!(path, expected);
+            // a subpath for every point, the lines of a cross or a plus being two
+            let moves = if ["cross", "plus"].contains(&marker) { 6 } else { 3 };
+            assert_eq!(path.matches('M').count(), moves, "{}", marker);
+            assert_eq!(scatter.contains("fill=\"none\""), moves == 6, "{}", marker);
+        }
+
+        assert_eq!(marker_path("square", (10.0, 10.0), 2.0), "M9 9h2v2h-2z");
+        assert_eq!(marker_path("plus", (10.0, 10.0), 2.0), "M9 10h2M10 9v2");
+        assert_eq!(marker_path("circle", (10.0, 10.0), 2.0), "M9 10a1 1 0 1 0 2 0a1 1 0 1 0 -2 0");
+
+        let errors = process("@scatter\n    points (1, 1)\n    marker \"star\"\n#end").1.unwrap_err();
+        assert_eq!(errors[0].message, "Unknown marker 'star'");
+        assert_eq!(errors[0].location.unwrap().line, 3);
+    }
+}
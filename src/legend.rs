@@ -0,0 +1,70 @@
+use crate::axis;
+
+/// Font size of the entries when the legend doesn't set one
+pub const FONT_SIZE: f64 = 8.0;
+/// Space between the border of the legend and its entries
+pub const PADDING: f64 = 4.0;
+/// Width of the sample of an element drawn before its name
+pub const SWATCH_WIDTH: f64 = 12.0;
+/// Space between a legend in a corner and the edges of the plot area
+pub const MARGIN: f64 = 4.0;
+/// Height of an entry, relative to the font size
+const LINE_HEIGHT: f64 = 1.4;
+
+/// Where the legend is drawn, over a corner of the plot area or next to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Position {
+    TopLeft,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Right,
+    Bottom,
+}
+
+impl Position {
+    pub const NAMES: [&'static str; 6] = ["top-left", "top-right", "bottom-left", "bottom-right", "right", "bottom"];
+
+    pub fn parse(name: &str) -> Option<Position> {
+        match name {
+            "top-left" => Some(Position::TopLeft),
+            "top-right" => Some(Position::TopRight),
+            "bottom-left" => Some(Position::BottomLeft),
+            "bottom-right" => Some(Position::BottomRight),
+            "right" => Some(Position::Right),
+            "bottom" => Some(Position::Bottom),
+            _ => None,
+        }
+    }
+}
+
+/// How an element is pictured in the legend
+#[derive(Debug, Clone, PartialEq)]
+pub enum Swatch {
+    Line { color: String, thickness: f64, dash: Option<String> },
+    Point { color: String },
+    Marker { marker: String, color: String },
+    /// the outline and the inside of a shape
+    Area { color: Option<String>, fill: Option<String>, alpha: f64 },
+}
+
+/// A named element listed in the legend
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub swatch: Swatch,
+}
+
+pub fn row_height(font_size: f64) -> f64 {
+    font_size * LINE_HEIGHT
+}
+
+/// Width and height of the legend listing `entries`
+pub fn size(entries: &[Entry], font_size: f64) -> (f64, f64) {
+    let names = entries.iter().map(|entry| axis::text_width(&entry.name, font_size)).fold(0.0, f64::max);
+    (
+        2.0 * PADDING + SWATCH_WIDTH + PADDING + names,
+        2.0 * PADDING + entries.len() as f64 * row_height(font_size),
+    )
+}
@@ -0,0 +1,192 @@
+//! The .g Graphs compiler
+//!
+//! ```
+//! let source = "
+//! #define x
+//!     max 100
+//! #end
+//! #define y
+//!     max 100
+//! #end
+//! #root
+//!     box 0, 0, 100, 100
+//!     axis x, y
+//! #end
+//! ";
+//!
+//! let output = gcompiler::compile(source, &gcompiler::Options::default()).unwrap();
+//! assert!(matches!(output, gcompiler::Output::Svg(_)));
+//! ```
+
+pub mod diagnostic;
+pub mod parser;
+pub mod expander;
+pub mod interpreter;
+pub mod transform;
+pub mod axis;
+pub mod legend;
+pub mod data;
+pub mod histogram;
+pub mod output;
+
+pub use diagnostic::{Code, Diagnostic};
+pub use output::Format;
+
+use interpreter::Interpreter;
+
+use std::path::PathBuf;
+
+/// Options of a compilation
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub format: Format,
+    /// Number of png pixels per svg unit
+    pub scale: f32,
+    /// Directory the data files are looked for in, the current one when
+    /// empty
+    pub base_dir: PathBuf,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            format: Format::Svg,
+            scale: 1.0,
+            base_dir: PathBuf::new(),
+        }
+    }
+}
+
+/// A compiled graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    Svg(String),
+    Png(Vec<u8>),
+}
+
+impl Output {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Output::Svg(svg) => svg.as_bytes(),
+            Output::Png(png) => png,
+        }
+    }
+}
+
+/// Compiles a .g program, returning every error found on failure
+pub fn compile(source: &str, options: &Options) -> Result<Output, Vec<Diagnostic>> {
+    let mut interpreter = Interpreter::new(source.to_string());
+    interpreter.base_dir = options.base_dir.clone();
+    let svg = interpreter.compile()?;
+
+    match options.format {
+        Format::Svg => Ok(Output::Svg(svg)),
+        Format::Png => output::render_png(&svg, options.scale)
+            .map(Output::Png)
+            .map_err(|e| vec![e]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+#define x
+    max 10
+#end
+#define y
+    max 10
+#end
+#root
+    box 0, 0, 100, 100
+    axis x, y
+#end
+";
+
+    #[test]
+    fn compiles_to_svg() {
+        let Ok(Output::Svg(svg)) = compile(SOURCE, &Options::default()) else {
+            panic!("the program should compile to svg");
+        };
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn compiles_to_png() {
+        let options = Options { format: Format::Png, ..Options::default() };
+        let output = compile(SOURCE, &options).unwrap();
+        assert!(output.as_bytes().starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn returns_errors_instead_of_exiting() {
+        let errors = compile("#define x\n    max 10\n#end", &Options::default()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Code::MissingKeyword);
+        assert_eq!(errors[0].message, "Missing 'root' declaration");
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let source = "
+$var a 1
+$var a 2
+#define x
+    max 10
+#end
+#define y
+    max 10
+#end
+#root
+    box 0, 0, 100
+    axis x, y
+#end
+@line
+    color 0xfff
+#end
+@point
+    at 1, 1
+    color 0xzz
+    size $b
+#end
+@point
+    at 2, 2
+    colr 0xfff
+#end
+";
+        let errors = compile(source, &Options::default()).unwrap_err();
+        let codes: Vec<Code> = errors.iter().map(|e| e.code).collect();
+        assert_eq!(codes, [
+            Code::Redefined,
+            Code::TupleArity,
+            Code::MissingKeyword,
+            Code::InvalidLiteral,
+            Code::Undefined,
+            Code::UnknownWord,
+        ]);
+    }
+
+    #[test]
+    fn faulty_value_before_end_is_reported_once() {
+        let root = "#define x\n    max 10\n#end\n#define y\n    max 10\n#end\n#root\n    box 0, 0, 100, 100\n    axis x, y\n#end\n";
+        for (value, code) in [("name $q", Code::Undefined), ("name .\"{q}\"", Code::Undefined), ("color 0xzz", Code::InvalidLiteral)] {
+            let source = format!("{}@point\n    at 1, 1\n    {}\n#end\n", root, value);
+            let errors = compile(&source, &Options::default()).unwrap_err();
+            let codes: Vec<Code> = errors.iter().map(|e| e.code).collect();
+            assert_eq!(codes, [code], "{}", value);
+        }
+    }
+
+    #[test]
+    fn colon_after_keyword_compiles_the_same() {
+        let with_colon = SOURCE.replace("max ", "max: ").replace("box ", "box: ");
+        assert_ne!(with_colon, SOURCE);
+        assert_eq!(compile(&with_colon, &Options::default()), compile(SOURCE, &Options::default()));
+
+        let errors = compile(&SOURCE.replace("max ", ": max "), &Options::default()).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|e| e.message == "Unexpected ':'"));
+    }
+}
@@ -1,47 +1,83 @@
 mod args;
-use args::{QArgs, Entity};
+use args::{QArgs, Entity, OutputArgs};
 use clap::Parser;
 
-mod parser;
-mod interpreter;
-use interpreter::Interpreter;
+use gcompiler::{output, Diagnostic, Format, Options, Output};
 
-use std::{path::PathBuf, process::exit};
+use std::{io::{IsTerminal, Write}, path::{Path, PathBuf}, process::exit};
 
 fn get_file_content(path: &PathBuf) -> String {
     let content = std::fs::read_to_string(path);
     match content {
-        Ok(c) => {
-            return c;
-        },
+        Ok(c) => c,
         Err(e) => {
-            println!("[ERROR]: {e} '{p}'", e=e, p=path.to_str().unwrap_or("unknown path"));
+            eprintln!("error: {e} '{p}'", e=e, p=path.to_str().unwrap_or("unknown path"));
             exit(1)
         },
     }
 }
 
+/// Prints the diagnostics with the part of `content` they point at
+fn report(diagnostics: Vec<Diagnostic>, content: &str, path: &str) -> ! {
+    for diagnostic in diagnostics.iter() {
+        eprintln!("{}", diagnostic.render(content, path));
+    }
+    match diagnostics.len() {
+        1 => eprintln!("error: could not compile '{}' due to 1 previous error", path),
+        n => eprintln!("error: could not compile '{}' due to {} previous errors", path, n),
+    }
+    exit(1)
+}
+
+/// `base_dir` being the directory the data files are looked for in
+fn write_output(content: &str, path: &str, base_dir: &Path, output: &OutputArgs) {
+    let options = Options {
+        format: output.format(),
+        scale: output.png_scale(),
+        base_dir: base_dir.to_path_buf(),
+    };
+    let compiled = match gcompiler::compile(content, &options) {
+        Ok(Output::Svg(svg)) => format!("{}\n", svg).into_bytes(),
+        Ok(Output::Png(png)) => png,
+        Err(diagnostics) => report(diagnostics, content, path),
+    };
+
+    match &output.output {
+        Some(path) if path.as_os_str() != "-" => {
+            if let Err(e) = output::write_atomic(path, &compiled) {
+                eprintln!("error: {e} '{p}'", e=e, p=path.to_str().unwrap_or("unknown path"));
+                exit(1);
+            }
+        },
+        _ => {
+            let mut stdout = std::io::stdout();
+            if options.format == Format::Png && stdout.is_terminal() {
+                eprintln!("error: Refusing to write png data to a terminal");
+                eprintln!("  = help: Use '--output' or redirect the output to a file");
+                exit(1);
+            }
+
+            if let Err(e) = stdout.write_all(&compiled) {
+                eprintln!("error: {}", e);
+                exit(1);
+            }
+        },
+    }
+}
+
 fn main() {
     let args = QArgs::parse();
     match args.entity {
         Entity::Compile(_c) => {
-            let content = get_file_content(&PathBuf::from(_c.path));
-            if content.len() == 0 {
-                return;
-            }
-        
-            let mut interpreter = Interpreter::new(content);
-            interpreter.compile();
-            
+            let content = get_file_content(&PathBuf::from(&_c.path));
+
+            // the data files are next to the source
+            let base_dir = Path::new(&_c.path).parent().unwrap_or(Path::new(""));
+            write_output(&content, &_c.path, base_dir, &_c.output);
         },
 
         Entity::Raw(_c) => {
-            if _c.input.len() == 0 {
-                return;
-            } else {
-                let mut interpreter = Interpreter::new(_c.input.to_string());
-                interpreter.compile();
-            }
+            write_output(&_c.input, "<input>", Path::new(""), &_c.output);
         }
     };
 
@@ -0,0 +1,121 @@
+use clap::ValueEnum;
+
+use crate::diagnostic::{Code, Diagnostic};
+
+use std::{fs, io::{self, Write}, path::Path};
+
+/// The formats a compiled graph can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Svg,
+    Png,
+}
+
+impl Format {
+    /// Infers the format from the extension of an output file
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "svg" => Some(Format::Svg),
+            "png" => Some(Format::Png),
+            _ => None,
+        }
+    }
+}
+
+/// Fonts looked for, in order, to draw the sans-serif texts of a png
+const SANS_SERIF_FAMILIES: [&str; 5] = ["Arial", "Helvetica", "DejaVu Sans", "Liberation Sans", "Noto Sans"];
+
+/// Rasterizes the generated svg, `scale` being the number of pixels per
+/// svg unit
+pub fn render_png(svg: &str, scale: f32) -> Result<Vec<u8>, Diagnostic> {
+    if !scale.is_finite() || scale <= 0.0 {
+        return Err(Diagnostic::error(Code::Output, format!("Invalid png scale {}", scale))
+            .with_note("The scale must be greater than 0"));
+    }
+
+    let mut options = resvg::usvg::Options::default();
+    let fontdb = options.fontdb_mut();
+    fontdb.load_system_fonts();
+    // the labels are written in the generic sans-serif family, which
+    // defaults to a font that most systems other than windows lack
+    let installed = |family: &&&str| fontdb.faces().any(|face| face.families.iter().any(|(name, _)| name == **family));
+    if let Some(family) = SANS_SERIF_FAMILIES.iter().find(installed) {
+        fontdb.set_sans_serif_family(*family);
+    }
+
+    let tree = resvg::usvg::Tree::from_str(svg, &options)
+        .map_err(|e| Diagnostic::error(Code::Output, format!("Cannot render the graph -> {}", e)))?;
+
+    let size = tree.size().to_int_size().scale_by(scale)
+        .ok_or(Diagnostic::error(Code::Output, format!("Cannot render the graph at scale {}", scale)))?;
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or(Diagnostic::error(Code::Output, format!("Cannot allocate a {}x{} image", size.width(), size.height())))?;
+
+    resvg::render(
+        &tree,
+        resvg::tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    pixmap.encode_png().map_err(|e| Diagnostic::error(Code::Output, format!("Cannot encode the png -> {}", e)))
+}
+
+/// Writes the file through a temporary sibling that is then renamed over
+/// the destination, so readers never see a partially written graph
+pub fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let file_name = path.file_name().ok_or(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "the output path is not a file",
+    ))?;
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let temporary = directory.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let result = fs::File::create(&temporary)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temporary, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_atomic_replaces_the_file() {
+        let directory = std::env::temp_dir().join(format!("gcompiler-output-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("graph.svg");
+        fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        // no temporary file is left next to it
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+
+        assert!(write_atomic(&directory.join("missing").join("graph.svg"), b"new").is_err());
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(Format::from_path(Path::new("graph.SVG")), Some(Format::Svg));
+        assert_eq!(Format::from_path(Path::new("graph.png")), Some(Format::Png));
+        assert_eq!(Format::from_path(Path::new("graph")), None);
+    }
+}
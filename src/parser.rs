@@ -1,6 +1,9 @@
-pub const TOP_LEVEL_DECLARATIONS: [&str; 4] = ["root", "grid", "define", "end"];
-pub const INNER_FUNCTIONS: [&str; 3] = ["line", "graph", "point"];
-pub const KEYWORDS: [&str; 14] = [
+use crate::diagnostic::{Code, Diagnostic};
+
+pub const TOP_LEVEL_DECLARATIONS: [&str; 5] = ["root", "grid", "legend", "define", "end"];
+pub const COMPTIME_DIRECTIVES: [&str; 2] = ["var", "for"];
+pub const INNER_FUNCTIONS: [&str; 11] = ["line", "graph", "point", "text", "rect", "circle", "polygon", "scatter", "polyline", "bars", "histogram"];
+pub const KEYWORDS: [&str; 51] = [
     "min",
     "max",
     "name",
@@ -14,10 +17,49 @@ pub const KEYWORDS: [&str; 14] = [
     "axis",
     "box",
     "at",
-    "step"
+    "step",
+    "samples",
+    "resolution",
+    "asymptotes",
+    "clip",
+    "minor",
+    "fontsize",
+    "format",
+    "scale",
+    "base",
+    "threshold",
+    "xstep",
+    "ystep",
+    "dash",
+    "title",
+    "subtitle",
+    "position",
+    "content",
+    "size",
+    "anchor",
+    "rotate",
+    "space",
+    "fill",
+    "radius",
+    "points",
+    "marker",
+    "data",
+    "columns",
+    "header",
+    "curve",
+    "categories",
+    "values",
+    "width",
+    "mode",
+    "colors",
+    "column",
+    "bins",
+    "density"
 ];
+pub const BOOLEANS: [&str; 2] = ["true", "false"];
 
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TokenType {
     STRING,
@@ -31,6 +73,20 @@ pub enum TokenType {
     VARNAME,
     VAR,
     DEFINE,
+    COMPTIME,
+    COMPVAR,
+    FSTRING,
+    BOOLEAN,
+    /// a word that isn't a keyword, either a loop variable or a typo,
+    /// which only the expander can tell apart
+    IDENTIFIER,
+}
+
+/// Byte offsets of a token in the source
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -38,29 +94,87 @@ pub struct Token {
     pub token_type: TokenType,
     pub value: String,
     pub line: usize,
+    pub column: usize,
+    pub span: Span,
 }
 
 pub struct Lexer {
-    input: String,
+    input: Vec<char>,
+    /// byte offset of every character, plus the length of the input
+    offsets: Vec<usize>,
     position: usize,
     line: usize,
+    line_start: usize,
+    /// position, line and column of the token being built
+    start: (usize, usize, usize),
     tokens: Vec<Token>,
     added_keywords: Vec<String>,
 }
 
 impl Lexer {
     pub fn new(input: String) -> Self {
+        let mut offsets: Vec<usize> = input.char_indices().map(|(i, _)| i).collect();
+        offsets.push(input.len());
         Lexer {
-            input,
+            input: input.chars().collect(),
+            offsets,
             position: 0,
             line: 1,
+            line_start: 0,
+            start: (0, 1, 1),
             tokens: Vec::new(),
             added_keywords: Vec::new(),
         }
     }
 
     fn consume(&mut self, len: usize) {
-        self.position += len;
+        for _ in 0..len {
+            if self.input.get(self.position) == Some(&'\n') {
+                self.line += 1;
+                self.line_start = self.position + 1;
+            }
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.input.get(self.position + offset).copied()
+    }
+
+    /// Marks the current position as the beginning of the next token
+    fn start_token(&mut self) {
+        self.start = (self.position, self.line, self.position - self.line_start + 1);
+    }
+
+    fn span(&self) -> Span {
+        let (start, _, _) = self.start;
+        let end = self.position.clamp(start, self.input.len());
+        Span {
+            start: self.offsets[start],
+            end: self.offsets[end],
+        }
+    }
+
+    /// An error pointing at the token being built
+    fn error(&self, code: Code, message: impl Into<String>) -> Diagnostic {
+        let (_, line, column) = self.start;
+        let token = Token {
+            token_type: TokenType::SYMBOL,
+            value: String::new(),
+            line,
+            column,
+            span: self.span(),
+        };
+        Diagnostic::error(code, message).at(&token)
+    }
+
+    fn skip_spaces(&mut self) {
+        while let Some(c) = self.peek(0) {
+            if c != ' ' && c != '\t' {
+                break;
+            }
+            self.consume(1);
+        }
     }
 
     pub fn get_human_readable(&mut self, token_type: TokenType) -> String {
@@ -76,23 +190,32 @@ impl Lexer {
             TokenType::VARNAME => String::from("variable name"),
             TokenType::VAR => String::from("variable"),
             TokenType::DEFINE => String::from("define"),
+            TokenType::COMPTIME => String::from("compile-time directive"),
+            TokenType::COMPVAR => String::from("compile-time variable"),
+            TokenType::FSTRING => String::from("interpolated string"),
+            TokenType::BOOLEAN => String::from("boolean"),
+            TokenType::IDENTIFIER => String::from("identifier"),
         }
     }
 
     fn make_token(&mut self, token_type: TokenType, value: String) {
+        let (_, line, column) = self.start;
+        let span = self.span();
         self.tokens.push(Token {
             token_type,
             value,
-            line: self.line,
+            line,
+            column,
+            span,
         });
     }
 
-    fn build_string(&mut self) {
+    fn build_string(&mut self, token_type: TokenType) {
         let mut string = String::new();
         let mut is_escaped = false;
         self.consume(1);
         while self.position < self.input.len() {
-            let c = self.input.chars().nth(self.position).unwrap();
+            let c = self.input[self.position];
             if c == '"' && !is_escaped {
                 self.consume(1);
                 break;
@@ -106,17 +229,25 @@ impl Lexer {
             is_escaped = false;
             self.consume(1);
         }
-        self.make_token(TokenType::STRING, string);
+        self.make_token(token_type, string);
     }
 
-    fn build_number(&mut self) -> Result<(), String> {
+    fn build_number(&mut self) -> Result<(), Diagnostic> {
         let mut number = String::new();
         let mut dot_encountered = false;
+        if self.peek(0) == Some('-') {
+            number.push('-');
+            self.consume(1);
+        }
         while self.position < self.input.len() {
-            let c = self.input.chars().nth(self.position).unwrap();
+            let c = self.input[self.position];
+            // `1..5` is a range, not a malformed float
+            if c == '.' && self.peek(1) == Some('.') {
+                break;
+            }
             if c == '.' {
                 if dot_encountered {
-                    return Err(format!("Invalid number at line {}", self.line));
+                    return Err(self.error(Code::InvalidLiteral, format!("Invalid number '{}.'", number)));
                 }
                 dot_encountered = true;
                 number.push(c);
@@ -138,46 +269,128 @@ impl Lexer {
         }
     }
 
-    fn build_hex(&mut self) {
+    fn build_hex(&mut self) -> Result<(), Diagnostic> {
         let mut hex = String::new();
         self.consume(2);
         while self.position < self.input.len() {
-            let c = self.input.chars().nth(self.position).unwrap();
+            let c = self.input[self.position];
             if !c.is_ascii_hexdigit() {
                 break;
             }
             hex.push(c);
             self.consume(1);
         }
+        // `0xfffz` or `0xgg` are typos, not a hex followed by a keyword
+        if self.peek(0).is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            while self.peek(0).is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                self.consume(1);
+            }
+            let literal: String = self.input[self.start.0..self.position].iter().collect();
+            return Err(self.error(Code::InvalidLiteral, format!("Invalid hexadecimal value '{}'", literal))
+                .with_note("Hexadecimal digits are 0-9 and a-f")
+                .with_help("Write colors with 3 or 6 hexadecimal digits, like 0xebdbb2 or 0xfff"));
+        }
+        if hex.is_empty() {
+            return Err(self.error(Code::InvalidLiteral, "Invalid hexadecimal value '0x'")
+                .with_note("No hexadecimal digits follow '0x'")
+                .with_help("Write colors with 3 or 6 hexadecimal digits, like 0xebdbb2 or 0xfff"));
+        }
         self.make_token(TokenType::HEX, hex);
+        Ok(())
     }
 
-    fn build_keyword(&mut self) -> Result<(), String>{
-        let mut keyword = String::new();
+    fn build_keyword(&mut self) -> Result<(), Diagnostic>{
+        let keyword = self.build_identifier();
+        if self.added_keywords.contains(&keyword) {
+            self.make_token(TokenType::VAR, keyword);
+            return Ok(());
+        }
+        if BOOLEANS.contains(&keyword.as_str()) {
+            self.make_token(TokenType::BOOLEAN, keyword);
+            return Ok(());
+        }
+        if KEYWORDS.contains(&keyword.as_str()) {
+            self.make_token(TokenType::KEYWORD, keyword);
+            // `color: 0x000000` is the same as `color 0x000000`
+            self.skip_spaces();
+            if self.peek(0) == Some(':') {
+                self.consume(1);
+            }
+        } else {
+            self.make_token(TokenType::IDENTIFIER, keyword);
+        }
+        Ok(())
+    }
+
+    /// Identifiers of compile-time variables may contain digits
+    /// and underscores after the first character
+    fn build_identifier(&mut self) -> String {
+        let mut identifier = String::new();
         while self.position < self.input.len() {
-            let c = self.input.chars().nth(self.position).unwrap();
-            if !c.is_alphabetic() {
+            let c = self.input[self.position];
+            if !(c.is_alphabetic() || (!identifier.is_empty() && (c.is_ascii_digit() || c == '_'))) {
                 break;
             }
-            keyword.push(c);
+            identifier.push(c);
             self.consume(1);
         }
-        if self.added_keywords.contains(&keyword) {
-            self.make_token(TokenType::VAR, keyword);
+        identifier
+    }
+
+    fn build_comptime(&mut self) -> Result<(), Diagnostic> {
+        self.consume(1);
+        let name = self.build_identifier();
+        if name.is_empty() {
+            return Err(self.error(Code::MissingValue, "Missing variable name after '$'"));
+        }
+
+        if !COMPTIME_DIRECTIVES.contains(&name.as_str()) {
+            self.make_token(TokenType::COMPVAR, name);
             return Ok(());
         }
-        if KEYWORDS.contains(&keyword.as_str()) {
-            self.make_token(TokenType::KEYWORD, keyword);
+
+        self.make_token(TokenType::COMPTIME, name.clone());
+        if name != "var" {
             return Ok(());
-        } else {
-            return Err(format!("Unknown keyword '{}' at line {}", keyword, self.line));
         }
+
+        self.skip_spaces();
+        self.start_token();
+        let varname = self.build_identifier();
+        if varname.is_empty() {
+            return Err(self.error(Code::MissingValue, format!("Missing variable name after '${}'", name)));
+        }
+        if COMPTIME_DIRECTIVES.contains(&varname.as_str()) {
+            return Err(self.error(Code::InvalidDirective, format!("'{}' is reserved and cannot be used as a variable name", varname)));
+        }
+        self.make_token(TokenType::VARNAME, varname);
+        Ok(())
+    }
+
+    /// The loop variable of `$for ... | i -> (...)` is referenced without
+    /// the `$` prefix inside the loop body, where the expander resolves it
+    fn build_loop_variable(&mut self) -> Result<(), Diagnostic> {
+        self.consume(1);
+        self.make_token(TokenType::SYMBOL, String::from("|"));
+        self.skip_spaces();
+
+        self.start_token();
+        let varname = self.build_identifier();
+        if varname.is_empty() {
+            return Err(self.error(Code::MissingValue, "Missing loop variable name after '|'"));
+        }
+        if KEYWORDS.contains(&varname.as_str()) || BOOLEANS.contains(&varname.as_str()) || self.added_keywords.contains(&varname) {
+            return Err(self.error(Code::InvalidDirective, format!("'{}' is already a keyword and cannot be used as a loop variable", varname)));
+        }
+
+        self.make_token(TokenType::VARNAME, varname);
+        Ok(())
     }
 
     fn skip_comment(&mut self) {
         self.consume(1);
         while self.position < self.input.len() {
-            let c = self.input.chars().nth(self.position).unwrap();
+            let c = self.input[self.position];
             if c == '%' {
                 self.consume(1);
                 break;
@@ -186,123 +399,224 @@ impl Lexer {
         }
     }
 
-    pub fn tokenize(&mut self) -> Result<Vec<Token>, String> {
-        while self.position < self.input.len() {
-            match self.input.chars().nth(self.position).unwrap() {
-                '"' => {
-                    self.build_string();
-                },
-                ' ' | '\t' => {
-                    self.consume(1);
-                },
-                '\n' => {
-                    self.consume(1);
-                    self.line += 1;
-                },
-                '0'..='9' => {
-                    // checking if it's a hex number
-                    if self.input.chars().nth(self.position).unwrap() == '0' && self.input.chars().nth(self.position + 1).unwrap() == 'x' {
-                        self.build_hex();
-                        continue;
-                    }
+    /// Builds the token starting at the current position
+    fn next_token(&mut self) -> Result<(), Diagnostic> {
+        match self.input[self.position] {
+            '"' => {
+                self.build_string(TokenType::STRING);
+            },
+            // interpolated strings are written ."L{i}"
+            '.' if self.peek(1) == Some('"') => {
+                self.consume(1);
+                self.build_string(TokenType::FSTRING);
+            },
+            ' ' | '\t' => {
+                self.consume(1);
+            },
+            '\n' => {
+                self.consume(1);
+            },
+            '0'..='9' => {
+                // checking if it's a hex number
+                if self.peek(0) == Some('0') && self.peek(1) == Some('x') {
+                    return self.build_hex();
+                }
 
-                    match self.build_number() {
-                        Ok(_) => {},
-                        Err(e) => {
-                            return Err(e);
-                        },
-                    }   
-                },
-
-                '.' => {
-                    if self.input.chars().nth(self.position + 1).unwrap().is_ascii_digit() {
-                        match self.build_number() {
-                            Ok(_) => {},
-                            Err(e) => {
-                                return Err(e);
-                            },
-                        }
-                    } else {
-                        self.consume(1);
-                    }
+                match self.build_number() {
+                    Ok(_) => {},
+                    Err(e) => {
+                        return Err(e);
+                    },
+                }
+            },
+
+            '.' if self.peek(1) == Some('.') => {
+                if self.peek(2) == Some('=') {
+                    self.consume(3);
+                    self.make_token(TokenType::SYMBOL, String::from("..="));
+                } else {
+                    self.consume(2);
+                    self.make_token(TokenType::SYMBOL, String::from(".."));
+                }
+            },
+
+            '.' if self.peek(1).is_some_and(|c| c.is_ascii_digit()) => {
+                match self.build_number() {
+                    Ok(_) => {},
+                    Err(e) => {
+                        return Err(e);
+                    },
+                }
+            }
+
+            '-' if self.peek(1) == Some('>') => {
+                self.consume(2);
+                self.make_token(TokenType::SYMBOL, String::from("->"));
+            },
+
+            '-' if self.peek(1).is_some_and(|c| c.is_ascii_digit())
+                || (self.peek(1) == Some('.') && self.peek(2).is_some_and(|c| c.is_ascii_digit())) => {
+                match self.build_number() {
+                    Ok(_) => {},
+                    Err(e) => {
+                        return Err(e);
+                    },
                 }
+            },
 
-                ',' => {
-                    self.make_token(TokenType::SYMBOL, self.input.chars().nth(self.position).unwrap().to_string());
+            ',' | '(' | ')' => {
+                let symbol = self.input[self.position].to_string();
+                self.consume(1);
+                self.make_token(TokenType::SYMBOL, symbol);
+            },
+            '|' => {
+                match self.build_loop_variable() {
+                    Ok(_) => {},
+                    Err(e) => {
+                        return Err(e);
+                    },
+                }
+            },
+            '#' => {
+                let mut declaration = String::new();
+                self.consume(1);
+                while self.position < self.input.len() {
+                    let c = self.input[self.position];
+                    if !c.is_alphabetic() {
+                        break;
+                    }
+                    declaration.push(c);
                     self.consume(1);
-                },
-                '#' => {
-                    let mut declaration = String::new();
+                }
+                if !TOP_LEVEL_DECLARATIONS.contains(&declaration.as_str()) {
+                    return Err(self.error(Code::UnknownWord, format!("Unknown declaration '{}'", declaration)));
+                }
+                let d = declaration.clone();
+                if  d == "define" {
+                    self.make_token(TokenType::DEFINE, declaration);
                     self.consume(1);
+                    self.start_token();
+                    let mut define = String::new();
                     while self.position < self.input.len() {
-                        let c = self.input.chars().nth(self.position).unwrap();
+                        let c = self.input[self.position];
                         if !c.is_alphabetic() {
                             break;
                         }
-                        declaration.push(c);
+                        define.push(c);
                         self.consume(1);
                     }
-                    if !TOP_LEVEL_DECLARATIONS.contains(&declaration.as_str()) {
-                        return Err(format!("Unknown declaration '{}'", declaration));
+                    if self.added_keywords.contains(&define) {
+                        return Err(self.error(Code::Redefined, format!("Keyword '{}' already defined", define)));
                     }
-                    let d = declaration.clone();
-                    if  d == "define" {
-                        self.make_token(TokenType::DEFINE, declaration);
-                        self.consume(1);
-                        let mut define = String::new();
-                        while self.position < self.input.len() {
-                            let c = self.input.chars().nth(self.position).unwrap();
-                            if !c.is_alphabetic() {
-                                break;
-                            }
-                            define.push(c);
-                            self.consume(1);
-                        }
-                        if self.added_keywords.contains(&define) {
-                            return Err(format!("Keyword '{}' already defined", define));
-                        }
 
-                        self.make_token(TokenType::VARNAME, define.clone());
-                        self.added_keywords.push(define);
-                    } else {
-                        self.make_token(TokenType::DECLARATION, declaration);
-                    }
+                    self.make_token(TokenType::VARNAME, define.clone());
+                    self.added_keywords.push(define);
+                } else {
+                    self.make_token(TokenType::DECLARATION, declaration);
+                }
 
-                },
-                '@' => {
-                    let mut function = String::new();
-                    self.consume(1);
-                    while self.position < self.input.len() {
-                        let c = self.input.chars().nth(self.position).unwrap();
-                        if !c.is_alphabetic() {
-                            break;
-                        }
-                        function.push(c);
-                        self.consume(1);
-                    }
-                    if !INNER_FUNCTIONS.contains(&function.as_str()) {
-                        return Err(format!("Unknown function '{}'", function));
-                    }
-                    self.make_token(TokenType::FUNCTION, function);
-                },
-                'a'..='z' | 'A'..='Z' => {
-                    match self.build_keyword() {
-                        Ok(_) => {},
-                        Err(e) => {
-                            return Err(e);
-                        },
+            },
+            '@' => {
+                let mut function = String::new();
+                self.consume(1);
+                while self.position < self.input.len() {
+                    let c = self.input[self.position];
+                    if !c.is_alphabetic() {
+                        break;
                     }
-                },
-                '%' => {
-                    self.skip_comment();
-                },
-                _ => {
+                    function.push(c);
                     self.consume(1);
-                },
-                
+                }
+                if !INNER_FUNCTIONS.contains(&function.as_str()) {
+                    return Err(self.error(Code::UnknownWord, format!("Unknown function '{}'", function)));
+                }
+                self.make_token(TokenType::FUNCTION, function);
+            },
+            'a'..='z' | 'A'..='Z' => {
+                match self.build_keyword() {
+                    Ok(_) => {},
+                    Err(e) => {
+                        return Err(e);
+                    },
+                }
+            },
+            '$' => {
+                match self.build_comptime() {
+                    Ok(_) => {},
+                    Err(e) => {
+                        return Err(e);
+                    },
+                }
+            },
+            '%' => {
+                self.skip_comment();
+            },
+            ':' => {
+                return Err(self.error(Code::UnexpectedToken, "Unexpected ':'").with_note("A ':' can only follow a keyword"));
+            },
+            _ => {
+                self.consume(1);
+            },
+
+        }
+
+        Ok(())
+    }
+
+    /// Tokenizes the whole input, going on after an error so that every
+    /// malformed word is reported along with the tokens around them
+    pub fn tokenize(&mut self) -> (Vec<Token>, Vec<Diagnostic>) {
+        let mut errors: Vec<Diagnostic> = Vec::new();
+        while self.position < self.input.len() {
+            self.start_token();
+            if let Err(e) = self.next_token() {
+                errors.push(e);
+                // the rest of the faulty word would only be reported again
+                while self.peek(0).is_some_and(|c| !c.is_whitespace()) {
+                    self.consume(1);
+                }
             }
         }
-        
-        Ok(self.tokens.clone())
+
+        (self.tokens.clone(), errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(source: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        Lexer::new(source.to_string()).tokenize()
+    }
+
+    #[test]
+    fn hex_without_digits_is_invalid() {
+        let (tokens, errors) = tokenize("color 0x\n");
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Code::InvalidLiteral);
+        assert_eq!(errors[0].message, "Invalid hexadecimal value '0x'");
+        let location = errors[0].location.unwrap();
+        assert_eq!((location.line, location.column, location.span.start, location.span.end), (1, 7, 6, 8));
+
+        let (tokens, errors) = tokenize("color 0xfff");
+        assert!(errors.is_empty());
+        assert_eq!(tokens[1].token_type, TokenType::HEX);
+        assert_eq!(tokens[1].value, "fff");
+    }
+
+    #[test]
+    fn colon_after_keyword_is_optional() {
+        let (with_colon, errors) = tokenize("max: 10\ncolor :0xfff");
+        assert!(errors.is_empty());
+        let (without_colon, _) = tokenize("max 10\ncolor 0xfff");
+        let values = |tokens: &[Token]| tokens.iter().map(|token| (token.token_type.clone(), token.value.clone())).collect::<Vec<_>>();
+        assert_eq!(values(&with_colon), values(&without_colon));
+
+        let (_, errors) = tokenize("max 10 : 5");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Code::UnexpectedToken);
+        assert_eq!(errors[0].message, "Unexpected ':'");
     }
 }
@@ -0,0 +1,212 @@
+use crate::axis::{self, LABEL_GAP, TICK_LENGTH};
+use crate::interpreter::Root;
+use crate::legend::{self, Position};
+
+/// Space left around the plot area given by the root box
+pub const PADDING: f64 = 10.0;
+/// Font sizes of the title and subtitle of the graph
+pub const TITLE_SIZE: f64 = 12.0;
+pub const SUBTITLE_SIZE: f64 = 9.0;
+
+/// A rectangle in svg coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Area {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// How the values of an axis are spread over the plot area
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Scale {
+    #[default]
+    Linear,
+    /// the powers of the base are evenly spaced
+    Log { base: f64 },
+    /// nearly linear between `-threshold` and `threshold`, and logarithmic
+    /// beyond them, for values of both signs
+    Symlog { base: f64, threshold: f64 },
+}
+
+impl Scale {
+    /// The position of a value along the axis, up to an affine map, which
+    /// isn't finite for the values out of the domain of the scale
+    pub fn apply(&self, value: f64) -> f64 {
+        match self {
+            Scale::Linear => value,
+            Scale::Log { base } => value.log(*base),
+            Scale::Symlog { base, threshold } => value.signum() * (1.0 + value.abs() / threshold).log(*base),
+        }
+    }
+
+    /// The value at a position given by `apply`
+    pub fn invert(&self, position: f64) -> f64 {
+        match self {
+            Scale::Linear => position,
+            Scale::Log { base } => base.powf(position),
+            Scale::Symlog { base, threshold } => position.signum() * threshold * (base.powf(position.abs()) - 1.0),
+        }
+    }
+}
+
+/// Maps data coordinates, in the ranges of the root axes, to svg
+/// coordinates in the plot area of the root box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    /// min and max of the x axis
+    pub x: (f64, f64),
+    /// min and max of the y axis
+    pub y: (f64, f64),
+    /// scales of the x and y axes
+    pub scales: (Scale, Scale),
+    pub area: Area,
+    /// the whole svg, the plot area and the margins around it for the
+    /// titles, the tick labels and a legend outside of the plot area
+    pub view: Area,
+    /// where the legend is drawn, if there is one
+    pub legend: Option<Area>,
+}
+
+impl Transform {
+    /// Lays out the graph around the plot area, `legend` being the
+    /// position and size of the legend if there is one
+    pub fn new(root: &Root, legend: Option<(Position, (f64, f64))>) -> Self {
+        let (x_axis, y_axis) = &root.axis;
+        let x = (x_axis.min.unwrap_or(0.0), x_axis.max);
+        let y = (y_axis.min.unwrap_or(0.0), y_axis.max);
+
+        // the labels only need a margin when their axis runs along the
+        // edge of the plot area, they are drawn inside it otherwise
+        let x_labels = match 0.0_f64.clamp(y.0, y.1) == y.0 {
+            true => TICK_LENGTH + LABEL_GAP + axis::font_size(x_axis),
+            false => 0.0,
+        };
+        let y_labels = match 0.0_f64.clamp(x.0, x.1) == x.0 {
+            true => TICK_LENGTH + LABEL_GAP + axis::labels_width(y_axis),
+            false => 0.0,
+        };
+        let x_title = x_axis.name.as_ref().map_or(0.0, |_| LABEL_GAP + axis::title_size(x_axis));
+        let y_title = y_axis.name.as_ref().map_or(0.0, |_| LABEL_GAP + axis::title_size(y_axis));
+
+        // the labels at the ends of the axes are centered on the edges of
+        // the plot area, half of them overflowing it
+        let x_labels_end = |label: Option<&(f64, String)>| label.map_or(0.0, |(_, label)| {
+            axis::text_width(label, axis::font_size(x_axis)) / 2.0 + LABEL_GAP
+        });
+        let labels = axis::labels(x_axis);
+        let y_end = axis::font_size(y_axis) / 2.0 + LABEL_GAP;
+
+        let header = root.title.as_ref().map_or(0.0, |_| TITLE_SIZE + LABEL_GAP)
+            + root.subtitle.as_ref().map_or(0.0, |_| SUBTITLE_SIZE + LABEL_GAP);
+
+        let left = PADDING + y_title + y_labels.max(x_labels_end(labels.first()));
+        let mut right = PADDING.max(x_labels_end(labels.last()));
+        let top = match header > 0.0 {
+            true => PADDING + header + y_end,
+            false => PADDING.max(y_end),
+        };
+        let mut bottom = PADDING + x_title + x_labels.max(y_end);
+        match legend {
+            Some((Position::Right, (width, _))) => right += width + PADDING,
+            Some((Position::Bottom, (_, height))) => bottom += height + PADDING,
+            _ => (),
+        }
+
+        let area = Area {
+            x: root._box.0 + left,
+            y: root._box.1 + top,
+            width: root._box.2,
+            height: root._box.3,
+        };
+        let view = Area {
+            x: root._box.0,
+            y: root._box.1,
+            width: root._box.2 + left + right,
+            height: root._box.3 + top + bottom,
+        };
+        let legend = legend.map(|(position, (width, height))| {
+            let left = area.x + legend::MARGIN;
+            let right = area.x + area.width - legend::MARGIN - width;
+            let top = area.y + legend::MARGIN;
+            let bottom = area.y + area.height - legend::MARGIN - height;
+            let (x, y) = match position {
+                Position::TopLeft => (left, top),
+                Position::TopRight => (right, top),
+                Position::BottomLeft => (left, bottom),
+                Position::BottomRight => (right, bottom),
+                Position::Right => (view.x + view.width - PADDING - width, area.y),
+                Position::Bottom => (area.x + (area.width - width) / 2.0, view.y + view.height - PADDING - height),
+            };
+            Area { x, y, width, height }
+        });
+
+        Transform {
+            x,
+            y,
+            scales: (x_axis.scale, y_axis.scale),
+            area,
+            view,
+            legend,
+        }
+    }
+
+    /// Where the axes cross, the origin being kept in the ranges
+    pub fn origin(&self) -> (f64, f64) {
+        (0.0_f64.clamp(self.x.0, self.x.1), 0.0_f64.clamp(self.y.0, self.y.1))
+    }
+
+    pub fn x(&self, x: f64) -> f64 {
+        let scale = self.scales.0;
+        let (min, max) = (scale.apply(self.x.0), scale.apply(self.x.1));
+        self.area.x + (scale.apply(x) - min) / (max - min) * self.area.width
+    }
+
+    /// svg coordinates go downward, so the y axis is flipped
+    pub fn y(&self, y: f64) -> f64 {
+        let scale = self.scales.1;
+        let (min, max) = (scale.apply(self.y.0), scale.apply(self.y.1));
+        self.area.y + self.area.height - (scale.apply(y) - min) / (max - min) * self.area.height
+    }
+
+    pub fn point(&self, point: (f64, f64)) -> (f64, f64) {
+        (self.x(point.0), self.y(point.1))
+    }
+
+    /// The data x of an svg x
+    pub fn data_x(&self, x: f64) -> f64 {
+        let scale = self.scales.0;
+        let (min, max) = (scale.apply(self.x.0), scale.apply(self.x.1));
+        scale.invert(min + (x - self.area.x) / self.area.width * (max - min))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() <= 1e-9 * b.abs().max(1.0), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn log_scale_spaces_powers_evenly() {
+        let scale = Scale::Log { base: 10.0 };
+        assert_close(scale.apply(1.0), 0.0);
+        assert_close(scale.apply(1000.0), 3.0);
+        assert_close(scale.invert(2.0), 100.0);
+        assert!(!scale.apply(0.0).is_finite());
+        assert!(scale.apply(-1.0).is_nan());
+    }
+
+    #[test]
+    fn symlog_scale_handles_both_signs() {
+        let scale = Scale::Symlog { base: 10.0, threshold: 1.0 };
+        assert_close(scale.apply(0.0), 0.0);
+        assert_close(scale.apply(9.0), 1.0);
+        assert_close(scale.apply(-99.0), -2.0);
+        for value in [-250.0, -0.5, 0.0, 0.25, 3.0, 1e6] {
+            assert_close(scale.invert(scale.apply(value)), value);
+        }
+    }
+}
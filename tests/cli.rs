@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+const SOURCE: &str = "
+#define x
+    max 10
+#end
+#define y
+    max 10
+#end
+#root
+    box 0, 0, 100, 100
+    axis x, y
+#end
+";
+
+/// A directory of its own for each test, emptied first
+fn directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("gcompiler-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn gcompiler() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_gcompiler"))
+}
+
+#[test]
+fn compile_writes_the_output_file() {
+    let directory = directory("output");
+    let source = directory.join("graph.g");
+    let output = directory.join("graph.svg");
+    std::fs::write(&source, SOURCE).unwrap();
+
+    let status = gcompiler().arg("compile").arg(&source).arg("-o").arg(&output).status().unwrap();
+    assert!(status.success());
+    let svg = std::fs::read_to_string(&output).unwrap();
+    assert!(svg.starts_with("<svg"));
+    // the temporary file is renamed over the output
+    let files: Vec<_> = std::fs::read_dir(&directory).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(files.len(), 2);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn empty_source_is_an_error() {
+    let directory = directory("empty");
+    let source = directory.join("empty.g");
+    let output = directory.join("empty.svg");
+    std::fs::write(&source, "").unwrap();
+
+    let result = gcompiler().arg("compile").arg(&source).arg("-o").arg(&output).output().unwrap();
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("Missing 'root' declaration"));
+    assert!(!output.exists());
+    std::fs::remove_dir_all(&directory).unwrap();
+}